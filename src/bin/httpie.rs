@@ -1,13 +1,13 @@
-use std::{collections::HashMap, sync::Arc, time::Instant};
+use std::time::Instant;
 use httpie::srv::Server;
-use httpie::srv::Request;
-use httpie::srv::Response;
+use httpie::srv::router::Router;
 
 mod route;
+mod signal;
 
-const DEFAULT_ADDRESS: &'static str = "127.0.0.1:8080";
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
 
-const CLI_HELP_MSG: &'static str = "httpie 0.1.1\n\
+const CLI_HELP_MSG: &str = "httpie 0.1.1\n\
     \n\
     USAGE:\n\
         main [OPTIONS]\n\
@@ -15,6 +15,8 @@ const CLI_HELP_MSG: &'static str = "httpie 0.1.1\n\
     FLAGS:\n\
         -h, --help       Prints help information\n\
         -V, --version    Prints version information\n\
+        -i, --autoindex  Lists directories without index.html\n\
+        -s, --spa        Serves index.html for unknown page paths\n\
     \n\
     OPTIONS:\n\
         -a, --address <ADDRESS>    Sets address:port\n\
@@ -22,15 +24,20 @@ const CLI_HELP_MSG: &'static str = "httpie 0.1.1\n\
     \n\
     httpie reads HTTPIE_ADDRESS environment variable";
 
+/// address, directory, help, version, autoindex, spa
+type Args = (
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>);
+
 fn main() {
 
     // command line parsing
     let args = {
-        let mut result: (
-            Option<String>,
-            Option<String>,
-            Option<String>,
-            Option<String>) = Default::default();
+        let mut result: Args = Default::default();
 
         for (index, arg) in std::env::args().enumerate() {
             if arg == "-a" || arg == "--address" {
@@ -47,7 +54,15 @@ fn main() {
             }
             if arg == "-V" || arg == "--version" {
                 if result.3.is_some() { panic!("Invalid arguments."); }
-                result.3 = Some(arg)
+                result.3 = Some(arg.clone())
+            }
+            if arg == "-i" || arg == "--autoindex" {
+                if result.4.is_some() { panic!("Invalid arguments."); }
+                result.4 = Some(arg.clone())
+            }
+            if arg == "-s" || arg == "--spa" {
+                if result.5.is_some() { panic!("Invalid arguments."); }
+                result.5 = Some(arg)
             }
         }
 
@@ -61,7 +76,7 @@ fn main() {
     }
 
     if args.3.is_some() {
-        println!("{}", "Version 0.1.1");
+        println!("Version 0.1.1");
         return;
     }
 
@@ -81,15 +96,28 @@ fn main() {
 
     let start_time = Instant::now();
 
-    Server::new()
+    let mut server = Server::new()
         .address(&address)
         .public(args.1.as_ref().unwrap_or(&"www".to_owned()))
         .max_connections(4)
-        .routes(Arc::new(HashMap::from([
-            ("/hello", Arc::new(route::hello_world) as Arc<dyn Fn(Request) -> Response + Send + Sync>),
-            ("/cwd", Arc::new(route::route_cwd))
-        ])))
-        .run();
+        .compression(true)
+        .precompressed(true)
+        .autoindex(args.4.is_some())
+        .routes(Router::new()
+            .get("/hello", route::hello_world)
+            .post("/hello", route::hello_world)
+            .get("/cwd", route::route_cwd));
+
+    if args.5.is_some() {
+        server = server.spa("index.html");
+    }
+
+    signal::shutdown_on_signal(server.shutdown_handle());
+
+    if let Err(err) = server.run() {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
 
     println!("Time elapsed: {} s. Shutting down...", start_time.elapsed().as_secs());
 }
@@ -2,27 +2,17 @@ use httpie::srv::Request;
 use httpie::srv::Response;
 use httpie::srv::Content;
 use httpie::srv::http;
+use httpie::srv::json::Json;
 
 pub fn hello_world(req: Request) -> Response {
 
-    Response {
-        body: Content::HeapString(format!(
-"{{
-    \"path\":{},
-    \"method\":{},
-    \"content-type\":{},
-    \"protocol\":{},
-    \"status\":{}
-}}",
-            req.path,
-            req.method.as_str(),
-            req.content_type.as_str(),
-            req.protocol.as_str(),
-            req.status.as_str(),
-        )),
-        status: http::StatusCode::Http200Ok,
-        content_type: http::ContentType::ApplicationJson
-    }
+    Response::json(&Json::object([
+        ("path", req.path.as_str().into()),
+        ("method", req.method.as_str().into()),
+        ("content-type", req.content_type.as_str().into()),
+        ("protocol", req.protocol.as_str().into()),
+        ("status", req.status.as_str().into())
+    ]))
 }
 
 pub fn route_cwd(_req: Request) -> Response {
@@ -31,15 +21,14 @@ pub fn route_cwd(_req: Request) -> Response {
         "Failed to get current working directory."
     );
 
-    Response {
-        body: match std::env::current_dir() {
+    Response::new(http::StatusCode::Http200Ok).with_body(
+        match std::env::current_dir() {
             Ok(res) => match res.into_os_string().into_string() {
                 Ok(res) => Content::HeapString(res),
                 Err(_) => ERROR_MSG
             },
             Err(_) => ERROR_MSG
         },
-        status: http::StatusCode::Http200Ok,
-        content_type: http::ContentType::TextPlain
-    }
+        http::ContentType::TextPlain
+    )
 }
\ No newline at end of file
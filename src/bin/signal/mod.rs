@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use httpie::srv::shutdown::ShutdownHandle;
+
+static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+/// Requests a graceful shutdown of the server when the process is interrupted or terminated.
+pub fn shutdown_on_signal(handle: ShutdownHandle) {
+
+    install();
+
+    // signal handlers may only do async-signal-safe work, so the flag is polled here
+    thread::spawn(move || {
+        while !RECEIVED.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(100));
+        }
+        println!("Signal received, finishing requests in flight...");
+        handle.shutdown();
+    });
+}
+
+#[cfg(unix)]
+fn install() {
+
+    const SIGINT: i32 = 2;
+    const SIGTERM: i32 = 15;
+
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+
+    extern "C" fn on_signal(_: i32) {
+        RECEIVED.store(true, Ordering::SeqCst);
+    }
+
+    unsafe {
+        signal(SIGINT, on_signal);
+        signal(SIGTERM, on_signal);
+    }
+}
+
+#[cfg(windows)]
+fn install() {
+
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: extern "system" fn(u32) -> i32, add: i32) -> i32;
+    }
+
+    extern "system" fn on_ctrl(_: u32) -> i32 {
+        RECEIVED.store(true, Ordering::SeqCst);
+        1
+    }
+
+    unsafe {
+        SetConsoleCtrlHandler(on_ctrl, 1);
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn install() {}
@@ -0,0 +1,46 @@
+use std::fmt;
+use std::io;
+
+use crate::srv::reader::ReadError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The server could not listen on the configured address.
+    Bind(String, io::Error),
+    /// A request could not be read from a connection.
+    Read(ReadError),
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Bind(address, err) => write!(f, "cannot bind address {}: {}", address, err),
+            Error::Read(err) => err.fmt(f),
+            Error::Io(err) => err.fmt(f)
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Bind(_, err) | Error::Io(err) => Some(err),
+            Error::Read(err) => Some(err)
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<ReadError> for Error {
+    fn from(err: ReadError) -> Self {
+        Error::Read(err)
+    }
+}
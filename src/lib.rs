@@ -1,2 +1,3 @@
 pub mod srv;
-pub mod pool;
\ No newline at end of file
+pub mod pool;
+pub mod error;
\ No newline at end of file
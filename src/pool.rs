@@ -1,20 +1,135 @@
-use std::sync::mpsc;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::Arc;
-use std::sync::Mutex;
+use std::sync::{Condvar, Mutex};
 use std::thread;
 
 pub struct ThreadPool {
     workers: Vec<Worker>,
-    sender: mpsc::Sender<Message>,
+    queue: Arc<JobQueue>,
 }
 
 type Job = Box<dyn FnOnce() + Send + Sync + 'static>;
 
+type Reject = Box<dyn FnOnce() + Send + 'static>;
+
 enum Message {
-    NewJob(Job),
+    NewJob(Job, Reject),
     Terminate,
 }
 
+/// What a bounded [`JobQueue`] does with a job that arrives while it is full.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// Waits until a worker takes a job off the queue.
+    #[default]
+    Block,
+    /// Refuses the new job.
+    Reject,
+    /// Refuses the job that waited longest and queues the new one.
+    DropOldest,
+}
+
+/// Jobs waiting for a free worker.
+///
+/// Shared between the pool and whoever wants to watch its depth.
+#[derive(Default)]
+pub struct JobQueue {
+    messages: Mutex<VecDeque<Message>>,
+    capacity: Option<usize>,
+    policy: QueuePolicy,
+    available: Condvar,
+    space: Condvar,
+}
+
+impl JobQueue {
+
+    pub fn unbounded() -> Self {
+        Self::default()
+    }
+
+    /// Create a queue holding up to `capacity` jobs, applying `policy` when full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the capacity is zero.
+    pub fn bounded(capacity: usize, policy: QueuePolicy) -> Self {
+        assert!(capacity > 0);
+
+        Self { capacity: Some(capacity), policy, ..Default::default() }
+    }
+
+    /// Number of jobs waiting for a worker.
+    pub fn len(&self) -> usize {
+        self.messages.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    pub fn policy(&self) -> QueuePolicy {
+        self.policy
+    }
+
+    fn push(&self, job: Job, reject: Reject) {
+
+        let mut messages = self.messages.lock().unwrap();
+
+        if let Some(capacity) = self.capacity {
+            match self.policy {
+                QueuePolicy::Block => {
+                    while messages.len() >= capacity {
+                        messages = self.space.wait(messages).unwrap();
+                    }
+                }
+                QueuePolicy::Reject if messages.len() >= capacity => {
+                    drop(messages);
+                    return reject();
+                }
+                QueuePolicy::DropOldest if messages.len() >= capacity => {
+                    let oldest = messages.pop_front();
+                    messages.push_back(Message::NewJob(job, reject));
+                    drop(messages);
+
+                    // the queue only holds terminate messages once the pool is dropping
+                    if let Some(Message::NewJob(_, reject)) = oldest {
+                        reject();
+                    }
+                    return self.available.notify_one();
+                }
+                _ => ()
+            }
+        }
+
+        messages.push_back(Message::NewJob(job, reject));
+        self.available.notify_one();
+    }
+
+    fn terminate(&self) {
+        self.messages.lock().unwrap().push_back(Message::Terminate);
+        self.available.notify_one();
+    }
+
+    fn pop(&self) -> Message {
+
+        let mut messages = self.messages.lock().unwrap();
+
+        loop {
+            if let Some(message) = messages.pop_front() {
+                self.space.notify_one();
+                return message;
+            }
+            messages = self.available.wait(messages).unwrap();
+        }
+    }
+}
+
 impl ThreadPool {
     /// Create a new ThreadPool.
     ///
@@ -24,28 +139,48 @@ impl ThreadPool {
     ///
     /// The `new` function will panic if the size is zero.
     pub fn new(size: usize) -> ThreadPool {
-        assert!(size > 0);
-
-        let (sender, receiver) = mpsc::channel();
+        ThreadPool::with_queue(size, Arc::new(JobQueue::unbounded()))
+    }
 
-        let receiver = Arc::new(Mutex::new(receiver));
+    /// Create a new ThreadPool taking its jobs from `queue`.
+    ///
+    /// # Panics
+    ///
+    /// The `with_queue` function will panic if the size is zero.
+    pub fn with_queue(size: usize, queue: Arc<JobQueue>) -> ThreadPool {
+        assert!(size > 0);
 
         let mut workers = Vec::with_capacity(size);
 
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+            workers.push(Worker::new(id, Arc::clone(&queue)));
         }
 
-        ThreadPool { workers, sender }
+        ThreadPool { workers, queue }
     }
 
     pub fn execute<F>(&self, f: F)
     where
         F: FnOnce() + Send + Sync + 'static,
     {
-        let job = Box::new(f);
+        self.execute_or_else(f, || ());
+    }
+
+    /// Queues `f`, or runs `on_reject` on the calling thread if the queue policy refuses it.
+    ///
+    /// With [`QueuePolicy::DropOldest`] the refused job may be an older one, in which case
+    /// its own `on_reject` runs.
+    pub fn execute_or_else<F, R>(&self, f: F, on_reject: R)
+    where
+        F: FnOnce() + Send + Sync + 'static,
+        R: FnOnce() + Send + 'static,
+    {
+        self.queue.push(Box::new(f), Box::new(on_reject));
+    }
 
-        self.sender.send(Message::NewJob(job)).unwrap();
+    /// Number of jobs waiting for a worker.
+    pub fn queue_depth(&self) -> usize {
+        self.queue.len()
     }
 }
 
@@ -54,7 +189,7 @@ impl Drop for ThreadPool {
         println!("Sending terminate message to all workers.");
 
         for _ in &self.workers {
-            self.sender.send(Message::Terminate).unwrap();
+            self.queue.terminate();
         }
 
         println!("Shutting down all workers.");
@@ -75,14 +210,18 @@ struct Worker {
 }
 
 impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
+    fn new(id: usize, queue: Arc<JobQueue>) -> Worker {
         let thread = thread::spawn(move || loop {
-            let message = receiver.lock().unwrap().recv().unwrap();
+            let message = queue.pop();
 
             match message {
-                Message::NewJob(job) => {
+                Message::NewJob(job, _) => {
                     // println!("Worker {} got a job; executing.", id);
-                    job();
+
+                    // a panicking job must not take the worker down with it
+                    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                        eprintln!("Worker {} recovered from a panicking job: {}", id, panic_message(&*payload));
+                    }
                 }
                 Message::Terminate => {
                     println!("Worker {} was told to terminate.", id);
@@ -97,3 +236,115 @@ impl Worker {
         }
     }
 }
+
+/// Message a panic was raised with, if it was raised with a string.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload.downcast_ref::<String>().map_or("<unknown>", String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn survives_panicking_jobs() {
+        let pool = ThreadPool::new(1);
+        let (sender, receiver) = mpsc::channel();
+
+        pool.execute(|| panic!("first"));
+        pool.execute(|| std::panic::panic_any(42));
+
+        pool.execute(move || sender.send("done").unwrap());
+
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok("done"));
+    }
+
+    type Log = Arc<Mutex<Vec<&'static str>>>;
+
+    /// Keeps the only worker of `pool` busy until the returned sender is dropped.
+    fn occupy(pool: &ThreadPool) -> mpsc::Sender<()> {
+        let (release, wait) = mpsc::channel::<()>();
+        let (started, running) = mpsc::channel();
+        let wait = Mutex::new(wait);
+
+        pool.execute(move || {
+            started.send(()).unwrap();
+            let _ = wait.lock().unwrap().recv();
+        });
+        running.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        release
+    }
+
+    fn note(log: &Log, entry: &'static str) -> impl FnOnce() + Send + Sync + 'static {
+        let log = Arc::clone(log);
+        move || log.lock().unwrap().push(entry)
+    }
+
+    /// Runs two jobs on a single worker with room for one waiting job.
+    fn overflow(policy: QueuePolicy) -> Vec<&'static str> {
+        let queue = Arc::new(JobQueue::bounded(1, policy));
+        let pool = ThreadPool::with_queue(1, Arc::clone(&queue));
+        let log = Log::default();
+
+        let release = occupy(&pool);
+        pool.execute_or_else(note(&log, "first"), note(&log, "first refused"));
+        pool.execute_or_else(note(&log, "second"), note(&log, "second refused"));
+        assert_eq!(queue.len(), 1);
+
+        // the pool finishes queued jobs before its workers stop
+        drop(release);
+        drop(pool);
+
+        let log = log.lock().unwrap().clone();
+        log
+    }
+
+    #[test]
+    fn refuses_new_jobs_when_full() {
+        assert_eq!(overflow(QueuePolicy::Reject), ["second refused", "first"]);
+    }
+
+    #[test]
+    fn refuses_the_oldest_job_when_full() {
+        assert_eq!(overflow(QueuePolicy::DropOldest), ["first refused", "second"]);
+    }
+
+    #[test]
+    fn waits_for_room_when_full() {
+        let queue = Arc::new(JobQueue::bounded(1, QueuePolicy::Block));
+        let pool = ThreadPool::with_queue(1, Arc::clone(&queue));
+        let log = Log::default();
+
+        let release = occupy(&pool);
+        pool.execute_or_else(note(&log, "first"), note(&log, "first refused"));
+
+        thread::scope(|scope| {
+            let blocked = scope.spawn(|| pool.execute_or_else(note(&log, "second"), note(&log, "second refused")));
+
+            thread::sleep(Duration::from_millis(100));
+            assert!(!blocked.is_finished());
+            assert_eq!(queue.len(), 1);
+
+            drop(release);
+            blocked.join().unwrap();
+        });
+
+        drop(pool);
+        assert_eq!(*log.lock().unwrap(), ["first", "second"]);
+    }
+
+    #[test]
+    fn reports_panic_messages() {
+        let message = |payload: Box<dyn Any + Send>| panic_message(&*payload).to_owned();
+
+        assert_eq!(message(Box::new("static")), "static");
+        assert_eq!(message(Box::new(String::from("formatted"))), "formatted");
+        assert_eq!(message(Box::new(42)), "<unknown>");
+    }
+}
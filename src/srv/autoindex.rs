@@ -0,0 +1,257 @@
+use std::cmp::Ordering;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+use super::files::resolve;
+use super::json::Json;
+use super::query::{percent_decode, percent_encode};
+use super::{date, Request, Response, Server};
+
+struct Entry {
+    name: String,
+    directory: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Name,
+    Size,
+    Modified,
+}
+
+impl Column {
+    const VALUES: [Self; 3] = [Self::Name, Self::Size, Self::Modified];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Column::Name => "name",
+            Column::Size => "size",
+            Column::Modified => "modified",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Column::Name => "Name",
+            Column::Size => "Size",
+            Column::Modified => "Last modified",
+        }
+    }
+}
+
+/// Lists `directory`, reached through the request path of `request`.
+///
+/// The listing is HTML, or JSON with `?format=json`, and is ordered by
+/// `?sort=name|size|modified` and `?order=asc|desc`, directories first.
+/// Hidden entries and entries the static file resolver would refuse are left out.
+pub fn render(server: &Server, root: &Path, request: &Request, directory: &Path) -> io::Result<Response> {
+
+    let param = |name: &str| request.query.get(name);
+
+    let column = Column::VALUES
+        .into_iter()
+        .find(|column| param("sort") == Some(column.as_str()))
+        .unwrap_or(Column::Name);
+    let descending = param("order") == Some("desc");
+
+    let mut entries = vec![];
+
+    for item in std::fs::read_dir(directory)? {
+        let item = item?;
+
+        let name = match item.file_name().into_string() {
+            Ok(name) if !name.starts_with('.') => name,
+            _ => continue
+        };
+
+        let path = format!("{}{}", request.path, percent_encode(&name));
+        let resolved = match resolve(root, &path, server.symlinks) {
+            Ok(resolved) => resolved,
+            Err(_) => continue
+        };
+
+        let metadata = resolved.metadata()?;
+
+        entries.push(Entry {
+            name,
+            directory: metadata.is_dir(),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        });
+    }
+
+    entries.sort_by(|a, b| {
+        let order = match column {
+            Column::Name => a.name.cmp(&b.name),
+            Column::Size => a.size.cmp(&b.size).then_with(|| a.name.cmp(&b.name)),
+            Column::Modified => a.modified.cmp(&b.modified).then_with(|| a.name.cmp(&b.name)),
+        };
+        let order = if descending { order.reverse() } else { order };
+
+        match (a.directory, b.directory) {
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            _ => order
+        }
+    });
+
+    let response = match param("format") {
+        Some("json") => Response::json(&render_json(&entries)),
+        _ => Response::html(render_html(request, &entries, column, descending))
+    };
+
+    Ok(response.with_header("Cache-Control", "no-cache"))
+}
+
+fn render_html(request: &Request, entries: &[Entry], column: Column, descending: bool) -> String {
+
+    let title = escape_html(&String::from_utf8_lossy(
+        &percent_decode(&request.path).unwrap_or_else(|| request.path.clone().into_bytes())
+    ));
+
+    let mut html = format!("<!DOCTYPE html>
+<html lang=\"en\">
+<head><meta charset=\"utf-8\"><title>Index of {0}</title></head>
+<body>
+<h1>Index of {0}</h1>
+<table>
+<thead><tr>", title);
+
+    for header in Column::VALUES {
+        // clicking the active column flips the order, other columns start ascending
+        let order = match header == column && !descending {
+            true => "desc",
+            false => "asc"
+        };
+        html.push_str(&format!(
+            "<th><a href=\"?sort={}&amp;order={}\">{}</a></th>",
+            header.as_str(), order, header.title()
+        ));
+    }
+
+    html.push_str("</tr></thead>\n<tbody>\n");
+
+    if request.path != "/" {
+        html.push_str("<tr><td><a href=\"../\">../</a></td><td>-</td><td>-</td></tr>\n");
+    }
+
+    for entry in entries {
+        let suffix = if entry.directory { "/" } else { "" };
+        let size = match entry.directory {
+            true => "-".to_owned(),
+            false => entry.size.to_string()
+        };
+        let modified = entry.modified.map(date::format).unwrap_or_else(|| "-".to_owned());
+
+        html.push_str(&format!(
+            "<tr><td><a href=\"{0}{2}\">{1}{2}</a></td><td>{3}</td><td>{4}</td></tr>\n",
+            escape_html(&percent_encode(&entry.name)), escape_html(&entry.name), suffix, size, modified
+        ));
+    }
+
+    html.push_str("</tbody>\n</table>\n</body>\n</html>\n");
+    html
+}
+
+fn render_json(entries: &[Entry]) -> Json {
+
+    let items = entries
+        .iter()
+        .map(|entry| Json::object([
+            ("name", entry.name.as_str().into()),
+            ("type", if entry.directory { "directory" } else { "file" }.into()),
+            ("size", entry.size.into()),
+            ("modified", entry.modified.map(date::format).into())
+        ]))
+        .collect();
+
+    Json::Array(items)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::super::reader::Limits;
+
+    /// Creates a public directory holding the given files and directories.
+    fn fixture(name: &str, files: &[(&str, usize)], directories: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("httpie-autoindex-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        for (file, size) in files {
+            fs::write(root.join(file), "x".repeat(*size)).unwrap();
+        }
+        for directory in directories {
+            fs::create_dir(root.join(directory)).unwrap();
+        }
+        root
+    }
+
+    /// Listing of `directory` below `root` requested with `query`.
+    fn listing(root: &Path, directory: &str, query: &str) -> String {
+        let path: Vec<String> = directory.split('/').map(percent_encode).collect();
+        let head = format!("GET /{}{} HTTP/1.1\r\n\r\n", path.join("/"), query);
+        let request = Request::from(&mut head.as_bytes(), &Limits::default()).unwrap();
+        let response = render(&Server::new(), root, &request, &root.join(directory)).unwrap();
+
+        String::from_utf8(response.body.as_bytes().to_vec()).unwrap()
+    }
+
+    /// Entry names in the order they are listed.
+    fn names(root: &Path, query: &str) -> Vec<String> {
+        let json = listing(root, "", &format!("?format=json&{}", query));
+
+        json.split("\"name\":\"").skip(1).map(|rest| rest[..rest.find('"').unwrap()].to_owned()).collect()
+    }
+
+    #[test]
+    fn escapes_names_and_the_title() {
+        let root = fixture("escape", &[], &["<script>"]);
+        fs::write(root.join("<script>/<b>&\"'.txt"), "").unwrap();
+        fs::write(root.join("<script>/a b.txt"), "").unwrap();
+        let html = listing(&root, "<script>/", "");
+
+        assert!(html.contains("<title>Index of /&lt;script&gt;/</title>"), "{}", html);
+        assert!(html.contains("<a href=\"%3Cb%3E%26%22%27.txt\">&lt;b&gt;&amp;&quot;&#39;.txt</a>"), "{}", html);
+        assert!(html.contains("<a href=\"a%20b.txt\">a b.txt</a>"), "{}", html);
+        assert!(!html.contains("<b>"), "{}", html);
+    }
+
+    #[test]
+    fn sorts_entries_with_directories_first() {
+        let root = fixture("sort", &[("b.txt", 3), ("a.txt", 10), ("c.txt", 1), (".hidden", 5)], &["z", "y"]);
+
+        assert_eq!(names(&root, ""), ["y", "z", "a.txt", "b.txt", "c.txt"]);
+        assert_eq!(names(&root, "order=desc"), ["z", "y", "c.txt", "b.txt", "a.txt"]);
+        assert_eq!(names(&root, "sort=size"), ["y", "z", "c.txt", "b.txt", "a.txt"]);
+        assert_eq!(names(&root, "sort=size&order=desc"), ["z", "y", "a.txt", "b.txt", "c.txt"]);
+        assert_eq!(names(&root, "sort=unknown"), ["y", "z", "a.txt", "b.txt", "c.txt"]);
+    }
+
+    #[test]
+    fn links_to_the_parent_and_the_other_order() {
+        let root = fixture("links", &[], &["docs"]);
+
+        assert!(!listing(&root, "", "").contains("href=\"../\""));
+        assert!(listing(&root, "docs/", "").contains("href=\"../\""));
+
+        let html = listing(&root, "", "?sort=size");
+        assert!(html.contains("href=\"?sort=size&amp;order=desc\""), "{}", html);
+        assert!(html.contains("href=\"?sort=name&amp;order=asc\""), "{}", html);
+    }
+}
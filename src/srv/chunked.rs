@@ -0,0 +1,161 @@
+use std::io::{self, BufRead, Write};
+
+use super::http::Headers;
+use super::reader::{parse_fields, read_fields, read_line, Limits, ReadError};
+
+/// Decodes a `chunked` request body, returning the content and the trailer fields.
+///
+/// Chunk extensions are ignored. The decoded size is bounded by `limits.max_body_size`,
+/// chunk-size lines and trailers by `limits.max_header_size`.
+pub fn read_chunked<R: BufRead>(reader: &mut R, limits: &Limits) -> Result<(Vec<u8>, Headers), ReadError> {
+
+    let mut content: Vec<u8> = vec![];
+
+    loop {
+        let mut line: Vec<u8> = vec![];
+        read_line(reader, &mut line, limits.max_header_size)?;
+
+        let line = std::str::from_utf8(&line).map_err(|_| ReadError::Malformed)?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+
+        if size.is_empty() || !size.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(ReadError::Malformed);
+        }
+
+        let size = match usize::from_str_radix(size, 16) {
+            Ok(size) => size,
+            Err(_) => return Err(ReadError::BodyTooLarge)
+        };
+
+        if size == 0 {
+            break;
+        }
+
+        if size > limits.max_body_size - content.len() {
+            return Err(ReadError::BodyTooLarge);
+        }
+
+        let start = content.len();
+        content.resize(start + size, 0);
+        reader.read_exact(&mut content[start..])?;
+
+        let mut crlf = [0; 2];
+        reader.read_exact(&mut crlf)?;
+
+        if &crlf != b"\r\n" {
+            return Err(ReadError::Malformed);
+        }
+    }
+
+    let mut block: Vec<u8> = vec![];
+    read_fields(reader, &mut block, limits.max_header_size)?;
+
+    let mut trailers = Headers::new();
+
+    if !parse_fields(&String::from_utf8_lossy(&block), &mut trailers) {
+        return Err(ReadError::Malformed);
+    }
+
+    Ok((content, trailers))
+}
+
+/// Writer that frames everything written to it as `chunked` transfer coding.
+///
+/// [`ChunkedWriter::finish`] has to be called to write the last chunk.
+pub struct ChunkedWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// Writes the terminating zero-size chunk and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(b"0\r\n\r\n")?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+
+        // an empty chunk would end the body
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        write!(self.inner, "{:X}\r\n", buf.len())?;
+        self.inner.write_all(buf)?;
+        self.inner.write_all(b"\r\n")?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(body: &str) -> Result<(Vec<u8>, Headers), ReadError> {
+        read_chunked(&mut body.as_bytes(), &Limits::default())
+    }
+
+    #[test]
+    fn decodes_chunks_and_trailers() {
+        let (content, trailers) = decode("4\r\nWiki\r\n6;name=value\r\npedia \r\nE\r\nin \r\n\r\nchunks.\r\n0\r\nExpires: never\r\n\r\n").unwrap();
+
+        assert_eq!(content, b"Wikipedia in \r\n\r\nchunks.");
+        assert_eq!(trailers.get("expires"), Some("never"));
+    }
+
+    #[test]
+    fn decodes_an_empty_body() {
+        let (content, trailers) = decode("0\r\n\r\n").unwrap();
+
+        assert!(content.is_empty());
+        assert!(trailers.get("Expires").is_none());
+    }
+
+    #[test]
+    fn refuses_malformed_framing() {
+        for body in [
+            "\r\n",
+            "x\r\nabc\r\n0\r\n\r\n",
+            "-1\r\na\r\n0\r\n\r\n",
+            "3\r\nabcd\r\n0\r\n\r\n",
+            "0\r\nno colon\r\n\r\n",
+        ] {
+            assert!(matches!(decode(body), Err(ReadError::Malformed)), "{:?}", body);
+        }
+
+        assert!(decode("3\r\nab").is_err());
+    }
+
+    #[test]
+    fn refuses_bodies_over_the_limit() {
+        let limits = Limits { max_body_size: 4, ..Limits::default() };
+
+        assert!(matches!(read_chunked(&mut "3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n".as_bytes(), &limits), Err(ReadError::BodyTooLarge)));
+        assert!(matches!(decode("fffffffffffffffffffff\r\n"), Err(ReadError::BodyTooLarge)));
+    }
+
+    #[test]
+    fn writer_output_decodes_back() {
+        let mut writer = ChunkedWriter::new(vec![]);
+        writer.write_all(b"hello ").unwrap();
+        writer.write_all(b"").unwrap();
+        writer.write_all(b"world").unwrap();
+        let framed = writer.finish().unwrap();
+
+        assert_eq!(framed, b"6\r\nhello \r\n5\r\nworld\r\n0\r\n\r\n");
+        assert_eq!(read_chunked(&mut framed.as_slice(), &Limits::default()).unwrap().0, b"hello world");
+    }
+}
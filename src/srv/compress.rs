@@ -0,0 +1,974 @@
+use std::io::{self, Read};
+
+use super::conditional::ETag;
+use super::http::*;
+use super::{Content, Response};
+
+/// Bodies shorter than this are sent as they are.
+pub const COMPRESSION_THRESHOLD: u64 = 256;
+
+/// Input consumed per DEFLATE block.
+const BLOCK_SIZE: usize = 64 * 1024;
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+const MAX_CHAIN: usize = 32;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+/// Content codings the server can produce, in order of preference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    pub const VALUES: [Self; 2] = [Self::Gzip, Self::Deflate];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+/// Picks the coding from `available` with the highest q-value in an
+/// `Accept-Encoding` field value, ties go to the earlier one in `available`.
+///
+/// Codings named with `q=0` or only covered by `*;q=0` are never chosen.
+pub fn negotiate<T: Copy>(accept_encoding: &str, available: &[(T, &str)]) -> Option<T> {
+
+    let mut preferences: Vec<(String, f32)> = vec![];
+
+    for item in accept_encoding.split(',') {
+        let mut params = item.split(';');
+        let coding = params.next().unwrap_or_default().trim().to_ascii_lowercase();
+
+        if coding.is_empty() {
+            continue;
+        }
+
+        let quality = params
+            .filter_map(|param| param.trim().split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+            .map(|(_, value)| value.trim().parse::<f32>().unwrap_or(0.0))
+            .unwrap_or(1.0);
+
+        // x-gzip is an alias kept for old clients
+        let coding = match coding.as_str() {
+            "x-gzip" => "gzip".to_owned(),
+            _ => coding
+        };
+
+        preferences.push((coding, quality));
+    }
+
+    let quality_of = |name: &str| {
+        preferences
+            .iter()
+            .find(|(coding, _)| coding == name)
+            .or_else(|| preferences.iter().find(|(coding, _)| coding == "*"))
+            .map(|(_, quality)| *quality)
+            .unwrap_or(0.0)
+    };
+
+    let mut best: Option<(T, f32)> = None;
+
+    for (value, name) in available {
+        let quality = quality_of(name);
+
+        if quality > 0.0 && best.is_none_or(|(_, best_quality)| quality > best_quality) {
+            best = Some((*value, quality));
+        }
+    }
+
+    best.map(|(value, _)| value)
+}
+
+/// Compresses the body of `response` with the coding the client prefers.
+///
+/// Only successful responses with a compressible media type, no `Content-Encoding`
+/// and no `Content-Range` are touched; those get `Vary: Accept-Encoding` whether they
+/// end up compressed or not. Buffered bodies stay buffered, files and streams are
+/// compressed while they are sent and lose their known length. A strong `ETag`
+/// is weakened, as the encoded bytes differ from the identity ones.
+pub fn encode_response(response: &mut Response, accept_encoding: Option<&str>) {
+
+    let content_type = match response.headers.get("Content-Type") {
+        Some(value) => ContentType::from_header(value),
+        None => response.content_type
+    };
+
+    if response.status != StatusCode::Http200Ok
+        || !content_type.is_compressible()
+        || response.headers.contains("Content-Encoding")
+        || response.headers.contains("Content-Range") {
+        return;
+    }
+
+    if !response.headers.has_token("Vary", "Accept-Encoding") {
+        response.headers.append("Vary", "Accept-Encoding");
+    }
+
+    if response.body.len().is_some_and(|length| length < COMPRESSION_THRESHOLD) {
+        return;
+    }
+
+    let available = Encoding::VALUES.map(|encoding| (encoding, encoding.as_str()));
+
+    let encoding = match accept_encoding.and_then(|value| negotiate(value, &available)) {
+        Some(encoding) => encoding,
+        None => return
+    };
+
+    response.body = match std::mem::replace(&mut response.body, Content::None) {
+        Content::Stream(reader, length) => Content::Stream(
+            Box::new(Encoder::new(reader.take(length.unwrap_or(u64::MAX)), encoding)),
+            None
+        ),
+        Content::File(file, length) => Content::Stream(
+            Box::new(Encoder::new(file.take(length), encoding)),
+            None
+        ),
+        body => Content::Raw(compress(body.as_bytes(), encoding))
+    };
+
+    response.headers.insert("Content-Encoding", encoding.as_str());
+
+    if let Some(etag) = response.headers.get("ETag").and_then(ETag::parse) {
+        response.headers.insert("ETag", ETag::weak(&etag.tag).to_string());
+    }
+}
+
+/// Compresses `data` in one go.
+pub fn compress(data: &[u8], encoding: Encoding) -> Vec<u8> {
+    let mut output = vec![];
+    Encoder::new(data, encoding)
+        .read_to_end(&mut output)
+        .expect("reading from a slice can not fail");
+    output
+}
+
+/// Reader that yields the compressed form of another reader.
+///
+/// Matches are found greedily through hash chains over a 32 KiB window and every
+/// 64 KiB of input becomes one dynamic Huffman block. Brotli is not produced,
+/// precompressed `.br` files can be served instead.
+pub struct Encoder<R: Read> {
+    inner: R,
+    encoding: Encoding,
+    deflater: Deflater,
+    crc: u32,
+    adler: (u32, u32),
+    size: u32,
+    output: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: Read> Encoder<R> {
+
+    pub fn new(inner: R, encoding: Encoding) -> Self {
+
+        let output = match encoding {
+            // no name, no time stamp, unknown OS
+            Encoding::Gzip => vec![0x1f, 0x8b, 0x08, 0, 0, 0, 0, 0, 0, 0xff],
+            Encoding::Deflate => vec![0x78, 0x01],
+        };
+
+        Self {
+            inner,
+            encoding,
+            deflater: Deflater::new(),
+            crc: 0,
+            adler: (1, 0),
+            size: 0,
+            output,
+            position: 0,
+            finished: false,
+        }
+    }
+
+    /// Compresses the next block of input, or writes the trailer at its end.
+    fn fill(&mut self) -> io::Result<()> {
+
+        let mut block = vec![0; BLOCK_SIZE];
+        let mut length = 0;
+
+        while length < BLOCK_SIZE {
+            match self.inner.read(&mut block[length..]) {
+                Ok(0) => break,
+                Ok(read) => length += read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err)
+            }
+        }
+
+        let block = &block[..length];
+
+        self.output.clear();
+        self.position = 0;
+
+        if !block.is_empty() {
+            self.crc = crc32(self.crc, block);
+            self.adler = adler32(self.adler, block);
+            self.size = self.size.wrapping_add(length as u32);
+            self.deflater.block(block, &mut self.output);
+        }
+
+        if length < BLOCK_SIZE {
+            self.deflater.finish(&mut self.output);
+
+            match self.encoding {
+                Encoding::Gzip => {
+                    self.output.extend_from_slice(&self.crc.to_le_bytes());
+                    self.output.extend_from_slice(&self.size.to_le_bytes());
+                }
+                Encoding::Deflate => {
+                    let (a, b) = self.adler;
+                    self.output.extend_from_slice(&((b << 16) | a).to_be_bytes());
+                }
+            }
+
+            self.finished = true;
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Encoder<R> {
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+
+        while self.position == self.output.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+
+        let available = &self.output[self.position..];
+        let count = available.len().min(buf.len());
+
+        buf[..count].copy_from_slice(&available[..count]);
+        self.position += count;
+
+        Ok(count)
+    }
+}
+
+/// Order in which the code length code lengths are stored in a dynamic block header.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Output of the LZ77 stage.
+#[derive(Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match { length: usize, distance: usize },
+}
+
+/// Raw DEFLATE stream writer, see RFC 1951.
+struct Deflater {
+    /// The last input bytes that later matches may refer to.
+    history: Vec<u8>,
+    bit_buffer: u64,
+    bit_count: u32,
+}
+
+impl Deflater {
+
+    fn new() -> Self {
+        Self { history: vec![], bit_buffer: 0, bit_count: 0 }
+    }
+
+    /// Appends one non-final dynamic Huffman block holding `block` to `output`.
+    fn block(&mut self, block: &[u8], output: &mut Vec<u8>) {
+        let tokens = self.tokenize(block);
+        self.write_dynamic(&tokens, output);
+    }
+
+    /// Appends an empty final block and pads the stream to a whole byte.
+    fn finish(&mut self, output: &mut Vec<u8>) {
+        // fixed Huffman block holding only the end-of-block code, seven zero bits
+        self.bits(1, 1, output);
+        self.bits(1, 2, output);
+        self.bits(0, 7, output);
+
+        if self.bit_count > 0 {
+            output.push(self.bit_buffer as u8);
+            self.bit_buffer = 0;
+            self.bit_count = 0;
+        }
+    }
+
+    /// Greedy LZ77 over `block`, matches may reach back into the previous blocks.
+    fn tokenize(&mut self, block: &[u8]) -> Vec<Token> {
+
+        let start = self.history.len();
+        let mut data = std::mem::take(&mut self.history);
+        data.extend_from_slice(block);
+
+        let mut head = vec![u32::MAX; 1 << HASH_BITS];
+        let mut prev = vec![u32::MAX; data.len()];
+
+        let insert = |head: &mut Vec<u32>, prev: &mut Vec<u32>, position: usize| {
+            if position + MIN_MATCH <= data.len() {
+                let hash = hash(&data[position..]);
+                prev[position] = head[hash];
+                head[hash] = position as u32;
+            }
+        };
+
+        for position in 0..start {
+            insert(&mut head, &mut prev, position);
+        }
+
+        let mut tokens = Vec::with_capacity(block.len() / 2);
+        let mut position = start;
+
+        while position < data.len() {
+
+            let (length, distance) = longest_match(&data, position, &head, &prev);
+
+            if length >= MIN_MATCH {
+                tokens.push(Token::Match { length, distance });
+
+                for next in position..position + length {
+                    insert(&mut head, &mut prev, next);
+                }
+                position += length;
+            } else {
+                tokens.push(Token::Literal(data[position]));
+                insert(&mut head, &mut prev, position);
+                position += 1;
+            }
+        }
+
+        let keep = data.len().saturating_sub(WINDOW_SIZE);
+        data.drain(..keep);
+        self.history = data;
+
+        tokens
+    }
+
+    fn write_dynamic(&mut self, tokens: &[Token], output: &mut Vec<u8>) {
+
+        let mut literal_frequencies = [0u32; 286];
+        let mut distance_frequencies = [0u32; 30];
+
+        for token in tokens {
+            match *token {
+                Token::Literal(byte) => literal_frequencies[byte as usize] += 1,
+                Token::Match { length, distance } => {
+                    literal_frequencies[257 + length_index(length)] += 1;
+                    distance_frequencies[distance_index(distance)] += 1;
+                }
+            }
+        }
+        literal_frequencies[256] = 1;
+
+        let literal_lengths = code_lengths(&literal_frequencies, 15);
+        let distance_lengths = code_lengths(&distance_frequencies, 15);
+        let literal_codes = canonical_codes(&literal_lengths);
+        let distance_codes = canonical_codes(&distance_lengths);
+
+        let literal_count = 257.max(literal_lengths.iter().rposition(|&length| length > 0).unwrap_or(0) + 1);
+        let distance_count = 1.max(distance_lengths.iter().rposition(|&length| length > 0).unwrap_or(0) + 1);
+
+        let mut lengths = literal_lengths[..literal_count].to_vec();
+        lengths.extend_from_slice(&distance_lengths[..distance_count]);
+
+        let runs = run_length_encode(&lengths);
+
+        let mut code_length_frequencies = [0u32; 19];
+        for &(symbol, _, _) in &runs {
+            code_length_frequencies[symbol as usize] += 1;
+        }
+
+        let code_length_lengths = code_lengths(&code_length_frequencies, 7);
+        let code_length_codes = canonical_codes(&code_length_lengths);
+        let code_length_count = 4.max(
+            CODE_LENGTH_ORDER.iter().rposition(|&symbol| code_length_lengths[symbol] > 0).unwrap_or(0) + 1
+        );
+
+        self.bits(0, 1, output);
+        self.bits(2, 2, output);
+        self.bits(literal_count as u32 - 257, 5, output);
+        self.bits(distance_count as u32 - 1, 5, output);
+        self.bits(code_length_count as u32 - 4, 4, output);
+
+        for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+            self.bits(code_length_lengths[symbol] as u32, 3, output);
+        }
+
+        for &(symbol, extra, extra_bits) in &runs {
+            let symbol = symbol as usize;
+            self.code(code_length_codes[symbol], code_length_lengths[symbol], output);
+            self.bits(extra, extra_bits, output);
+        }
+
+        for token in tokens {
+            match *token {
+                Token::Literal(byte) => {
+                    let symbol = byte as usize;
+                    self.code(literal_codes[symbol], literal_lengths[symbol], output);
+                }
+                Token::Match { length, distance } => {
+                    let index = length_index(length);
+                    self.code(literal_codes[257 + index], literal_lengths[257 + index], output);
+                    self.bits((length - LENGTH_BASE[index] as usize) as u32, LENGTH_EXTRA[index] as u32, output);
+
+                    let index = distance_index(distance);
+                    self.code(distance_codes[index], distance_lengths[index], output);
+                    self.bits((distance - DISTANCE_BASE[index] as usize) as u32, DISTANCE_EXTRA[index] as u32, output);
+                }
+            }
+        }
+
+        self.code(literal_codes[256], literal_lengths[256], output);
+    }
+
+    fn bits(&mut self, value: u32, count: u32, output: &mut Vec<u8>) {
+        self.bit_buffer |= (value as u64) << self.bit_count;
+        self.bit_count += count;
+
+        while self.bit_count >= 8 {
+            output.push(self.bit_buffer as u8);
+            self.bit_buffer >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    /// Huffman codes are packed starting from their most significant bit.
+    fn code(&mut self, code: u16, length: u8, output: &mut Vec<u8>) {
+        let reversed = (code as u32).reverse_bits() >> (32 - length as u32);
+        self.bits(reversed, length as u32, output);
+    }
+}
+
+fn length_index(length: usize) -> usize {
+    LENGTH_BASE.iter().rposition(|&base| base as usize <= length).unwrap_or(0)
+}
+
+fn distance_index(distance: usize) -> usize {
+    DISTANCE_BASE.iter().rposition(|&base| base as usize <= distance).unwrap_or(0)
+}
+
+/// Huffman code lengths no longer than `limit` for the given symbol frequencies.
+///
+/// At least two symbols always get a code, as some decoders refuse single-code trees.
+/// Frequencies are flattened until the tree fits the limit.
+fn code_lengths(frequencies: &[u32], limit: u8) -> Vec<u8> {
+
+    let mut frequencies = frequencies.to_vec();
+
+    for symbol in 0..frequencies.len() {
+        if frequencies.iter().filter(|&&frequency| frequency > 0).count() >= 2 {
+            break;
+        }
+        if frequencies[symbol] == 0 {
+            frequencies[symbol] = 1;
+        }
+    }
+
+    loop {
+        let lengths = huffman_lengths(&frequencies);
+
+        if lengths.iter().all(|&length| length <= limit) {
+            return lengths;
+        }
+
+        for frequency in frequencies.iter_mut().filter(|frequency| **frequency > 0) {
+            *frequency = frequency.div_ceil(2);
+        }
+    }
+}
+
+/// Depth of every symbol in a Huffman tree built over `frequencies`, zero for unused symbols.
+fn huffman_lengths(frequencies: &[u32]) -> Vec<u8> {
+
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    let mut parents: Vec<usize> = vec![usize::MAX; frequencies.len()];
+    let mut heap = BinaryHeap::new();
+
+    for (symbol, &frequency) in frequencies.iter().enumerate() {
+        if frequency > 0 {
+            heap.push(Reverse((frequency as u64, symbol)));
+        }
+    }
+
+    while heap.len() > 1 {
+        let Reverse((first_weight, first)) = heap.pop().unwrap_or_default();
+        let Reverse((second_weight, second)) = heap.pop().unwrap_or_default();
+
+        let node = parents.len();
+        parents.push(usize::MAX);
+        parents[first] = node;
+        parents[second] = node;
+
+        heap.push(Reverse((first_weight + second_weight, node)));
+    }
+
+    (0..frequencies.len())
+        .map(|symbol| match frequencies[symbol] {
+            0 => 0,
+            _ => {
+                let mut depth = 0;
+                let mut node = symbol;
+                while parents[node] != usize::MAX {
+                    node = parents[node];
+                    depth += 1;
+                }
+                depth
+            }
+        })
+        .collect()
+}
+
+/// Canonical Huffman codes for the given code lengths (RFC 1951, section 3.2.2).
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+
+    let mut counts = [0u16; 16];
+    for &length in lengths {
+        counts[length as usize] += 1;
+    }
+    counts[0] = 0;
+
+    let mut next = [0u16; 16];
+    let mut code = 0;
+    for bits in 1..16 {
+        code = (code + counts[bits - 1]) << 1;
+        next[bits] = code;
+    }
+
+    lengths
+        .iter()
+        .map(|&length| match length {
+            0 => 0,
+            _ => {
+                let code = next[length as usize];
+                next[length as usize] += 1;
+                code
+            }
+        })
+        .collect()
+}
+
+/// Encodes code lengths with the repeat symbols 16, 17 and 18, as
+/// `(symbol, extra value, extra bit count)`.
+fn run_length_encode(lengths: &[u8]) -> Vec<(u8, u32, u32)> {
+
+    let mut runs = vec![];
+    let mut index = 0;
+
+    while index < lengths.len() {
+
+        let length = lengths[index];
+        let run = lengths[index..].iter().take_while(|&&other| other == length).count();
+
+        if length == 0 && run >= 11 {
+            let run = run.min(138);
+            runs.push((18, run as u32 - 11, 7));
+            index += run;
+        } else if length == 0 && run >= 3 {
+            runs.push((17, run as u32 - 3, 3));
+            index += run;
+        } else if length > 0 && run >= 4 {
+            // the first one is sent as is, the repeat covers 3 to 6 copies of it
+            let repeat = (run - 1).min(6);
+            runs.push((length, 0, 0));
+            runs.push((16, repeat as u32 - 3, 2));
+            index += 1 + repeat;
+        } else {
+            runs.push((length, 0, 0));
+            index += 1;
+        }
+    }
+
+    runs
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]);
+    (value.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+/// Longest earlier occurrence of the bytes at `position`, as `(length, distance)`.
+fn longest_match(data: &[u8], position: usize, head: &[u32], prev: &[u32]) -> (usize, usize) {
+
+    if position + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+
+    let limit = (data.len() - position).min(MAX_MATCH);
+    let mut candidate = head[hash(&data[position..])];
+    let mut best = (0, 0);
+
+    for _ in 0..MAX_CHAIN {
+
+        if candidate == u32::MAX || position - candidate as usize > WINDOW_SIZE {
+            break;
+        }
+
+        let earlier = candidate as usize;
+
+        // a longer match has to at least agree on the byte after the current best
+        if best.0 > 0 && data[earlier + best.0] != data[position + best.0] {
+            candidate = prev[earlier];
+            continue;
+        }
+
+        let length = data[earlier..]
+            .iter()
+            .zip(&data[position..position + limit])
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        if length > best.0 {
+            best = (length, position - earlier);
+            if length == limit {
+                break;
+            }
+        }
+
+        candidate = prev[earlier];
+    }
+
+    best
+}
+
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc = CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+fn adler32((mut a, mut b): (u32, u32), data: &[u8]) -> (u32, u32) {
+    // 5552 bytes is the most that can be summed before the modulo is due
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Reads a DEFLATE stream least significant bit first.
+    struct Bits<'a> {
+        data: &'a [u8],
+        position: usize,
+    }
+
+    impl Bits<'_> {
+        fn bits(&mut self, count: u32) -> u32 {
+            let mut value = 0;
+            for i in 0..count {
+                let bit = self.data[self.position / 8] >> (self.position % 8) & 1;
+                value |= (bit as u32) << i;
+                self.position += 1;
+            }
+            value
+        }
+
+        fn symbol(&mut self, code: &Code) -> usize {
+            let (mut bits, mut length) = (0, 0);
+
+            loop {
+                bits = bits << 1 | self.bits(1);
+                length += 1;
+                assert!(length <= 15, "invalid code");
+
+                if let Some(&symbol) = code.get(&(bits, length)) {
+                    return symbol;
+                }
+            }
+        }
+    }
+
+    /// Symbols by their (code, length).
+    type Code = HashMap<(u32, u8), usize>;
+
+    /// Canonical Huffman code for the given code lengths, RFC 1951 section 3.2.2.
+    fn canonical(lengths: &[u8]) -> Code {
+        let mut counts = [0u32; 16];
+        for &length in lengths.iter().filter(|&&length| length > 0) {
+            counts[length as usize] += 1;
+        }
+
+        let mut next = [0u32; 16];
+        let mut code = 0;
+
+        for length in 1..16 {
+            code = (code + counts[length - 1]) << 1;
+            next[length] = code;
+        }
+
+        let mut symbols = Code::new();
+
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length > 0 {
+                symbols.insert((next[length as usize], length), symbol);
+                next[length as usize] += 1;
+            }
+        }
+        symbols
+    }
+
+    /// Straightforward decoder for raw DEFLATE, kept apart from the encoder's code.
+    fn inflate(data: &[u8]) -> Vec<u8> {
+        let mut bits = Bits { data, position: 0 };
+        let mut output: Vec<u8> = vec![];
+
+        loop {
+            let last = bits.bits(1) == 1;
+
+            let (literals, distances) = match bits.bits(2) {
+                0 => {
+                    bits.position = bits.position.div_ceil(8) * 8;
+                    let length = bits.bits(16);
+                    assert_eq!(bits.bits(16), !length & 0xffff);
+                    for _ in 0..length {
+                        output.push(bits.bits(8) as u8);
+                    }
+                    match last {
+                        true => return output,
+                        false => continue
+                    }
+                }
+                1 => {
+                    let mut literals = vec![8; 144];
+                    literals.extend([9; 112]);
+                    literals.extend([7; 24]);
+                    literals.extend([8; 8]);
+                    (canonical(&literals), canonical(&[5; 30]))
+                }
+                2 => {
+                    let literal_count = bits.bits(5) as usize + 257;
+                    let distance_count = bits.bits(5) as usize + 1;
+                    let length_count = bits.bits(4) as usize + 4;
+
+                    let mut code_lengths = [0; 19];
+                    for &symbol in &CODE_LENGTH_ORDER[..length_count] {
+                        code_lengths[symbol] = bits.bits(3) as u8;
+                    }
+                    let code_lengths = canonical(&code_lengths);
+
+                    let mut lengths: Vec<u8> = vec![];
+                    while lengths.len() < literal_count + distance_count {
+                        match bits.symbol(&code_lengths) {
+                            symbol @ 0..=15 => lengths.push(symbol as u8),
+                            16 => {
+                                let previous = *lengths.last().unwrap();
+                                lengths.extend(std::iter::repeat_n(previous, 3 + bits.bits(2) as usize));
+                            }
+                            17 => lengths.extend(std::iter::repeat_n(0, 3 + bits.bits(3) as usize)),
+                            _ => lengths.extend(std::iter::repeat_n(0, 11 + bits.bits(7) as usize))
+                        }
+                    }
+                    assert_eq!(lengths.len(), literal_count + distance_count);
+
+                    let distances = lengths.split_off(literal_count);
+                    (canonical(&lengths), canonical(&distances))
+                }
+                _ => panic!("reserved block type")
+            };
+
+            loop {
+                match bits.symbol(&literals) {
+                    literal @ 0..=255 => output.push(literal as u8),
+                    256 => break,
+                    symbol => {
+                        let index = symbol - 257;
+                        let length = LENGTH_BASE[index] as usize + bits.bits(LENGTH_EXTRA[index] as u32) as usize;
+                        let index = bits.symbol(&distances);
+                        let distance = DISTANCE_BASE[index] as usize + bits.bits(DISTANCE_EXTRA[index] as u32) as usize;
+
+                        assert!(distance <= output.len() && distance <= WINDOW_SIZE);
+                        for _ in 0..length {
+                            output.push(output[output.len() - distance]);
+                        }
+                    }
+                }
+            }
+
+            if last {
+                return output;
+            }
+        }
+    }
+
+    fn gunzip(data: &[u8]) -> Vec<u8> {
+        assert_eq!(data[..4], [0x1f, 0x8b, 0x08, 0]);
+
+        let output = inflate(&data[10..data.len() - 8]);
+        let trailer = &data[data.len() - 8..];
+
+        assert_eq!(trailer[..4], crc32(0, &output).to_le_bytes());
+        assert_eq!(trailer[4..], (output.len() as u32).to_le_bytes());
+        output
+    }
+
+    fn unzlib(data: &[u8]) -> Vec<u8> {
+        assert_eq!(u16::from_be_bytes([data[0], data[1]]) % 31, 0);
+        assert_eq!(data[0] & 0x0f, 8);
+
+        let output = inflate(&data[2..data.len() - 4]);
+        let (a, b) = adler32((1, 0), &output);
+
+        assert_eq!(data[data.len() - 4..], ((b << 16) | a).to_be_bytes());
+        output
+    }
+
+    /// Pseudo-random bytes that do not compress.
+    fn noise(length: usize, mut seed: u32) -> Vec<u8> {
+        (0..length)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn computes_checksums() {
+        assert_eq!(crc32(0, b""), 0);
+        assert_eq!(crc32(0, b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xCBF4_3926);
+
+        let (a, b) = adler32((1, 0), b"Wikipedia");
+        assert_eq!((b << 16) | a, 0x11E6_0398);
+
+        // sums that need the modulo more than once
+        let data = vec![0xff; 100_000];
+        let (a, b) = adler32((1, 0), &data);
+        let (split_a, split_b) = adler32(adler32((1, 0), &data[..7]), &data[7..]);
+        assert_eq!((a, b), (split_a, split_b));
+        assert_eq!((b << 16) | a, 0x149A_302C);
+    }
+
+    #[test]
+    fn decodes_streams_of_another_encoder() {
+        // zlib.compress(b"hello hello hello hello, deflate", 9) and zlib.compress(b"stored", 0)
+        let fixed = [
+            0x78, 0xda, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0x75, 0x14, 0x52,
+            0x52, 0xd3, 0x72, 0x12, 0x4b, 0x52, 0x01, 0xc4, 0x00, 0x0b, 0xd2,
+        ];
+        let stored = [0x78, 0x01, 0x01, 0x06, 0x00, 0xf9, 0xff, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64, 0x09, 0x3c, 0x02, 0x92];
+
+        assert_eq!(unzlib(&fixed), b"hello hello hello hello, deflate");
+        assert_eq!(unzlib(&stored), b"stored");
+    }
+
+    #[test]
+    fn round_trips_across_block_and_window_boundaries() {
+        let text = b"GET /index.html HTTP/1.1\r\nAccept-Encoding: gzip\r\n".repeat(3000);
+
+        // a repeat just inside the window and one just beyond it
+        let mut repeats = noise(WINDOW_SIZE - 10, 1);
+        repeats.extend_from_within(..);
+        repeats.extend(noise(20, 2));
+        repeats.extend_from_within(..WINDOW_SIZE + 10);
+
+        let inputs = [
+            vec![],
+            vec![b'a'],
+            vec![b'a'; MAX_MATCH * 3 + 1],
+            noise(BLOCK_SIZE - 1, 3),
+            noise(BLOCK_SIZE, 4),
+            noise(BLOCK_SIZE + 1, 5),
+            text[..BLOCK_SIZE * 2 + 7].to_vec(),
+            repeats,
+        ];
+
+        for input in inputs {
+            assert_eq!(gunzip(&compress(&input, Encoding::Gzip)), input, "{} bytes", input.len());
+            assert_eq!(unzlib(&compress(&input, Encoding::Deflate)), input, "{} bytes", input.len());
+        }
+
+        assert!(compress(&text, Encoding::Gzip).len() < text.len() / 50);
+    }
+
+    #[test]
+    fn negotiates_codings() {
+        let available = [(Encoding::Gzip, "gzip"), (Encoding::Deflate, "deflate")];
+        let negotiate = |accept_encoding| negotiate(accept_encoding, &available);
+
+        assert_eq!(negotiate("gzip, deflate"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("deflate"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("GZIP"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("x-gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br"), None);
+        assert_eq!(negotiate(""), None);
+
+        // q-values
+        assert_eq!(negotiate("gzip;q=0.5, deflate"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("gzip ; Q=0.8 , deflate;q=0.9"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("gzip;q=0, deflate;q=0"), None);
+        assert_eq!(negotiate("gzip;q=0"), None);
+        assert_eq!(negotiate("gzip;q=invalid"), None);
+
+        // wildcard
+        assert_eq!(negotiate("*"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("*;q=0"), None);
+        assert_eq!(negotiate("*;q=0, deflate"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("gzip;q=0, *"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("*;q=0.5, deflate;q=0.4"), Some(Encoding::Gzip));
+
+        // identity is not a coding the server produces, refusing it changes nothing
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate("identity;q=0"), None);
+        assert_eq!(negotiate("identity;q=0, deflate;q=0.1"), Some(Encoding::Deflate));
+
+        // ties go to the earlier available coding
+        assert_eq!(negotiate("deflate, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("deflate;q=0.5, gzip;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("deflate;q=0.5, *;q=0.5"), Some(Encoding::Gzip));
+    }
+}
@@ -0,0 +1,296 @@
+use std::fmt;
+use std::time::SystemTime;
+
+use super::http::*;
+use super::{date, Request, Response};
+
+/// Opaque entity tag, stored without the quotes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ETag {
+    pub weak: bool,
+    pub tag: String,
+}
+
+impl ETag {
+
+    pub fn strong(tag: &str) -> Self {
+        Self { weak: false, tag: tag.to_owned() }
+    }
+
+    pub fn weak(tag: &str) -> Self {
+        Self { weak: true, tag: tag.to_owned() }
+    }
+
+    /// Parses `"tag"` or `W/"tag"`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (weak, quoted) = match value.strip_prefix("W/") {
+            Some(quoted) => (true, quoted),
+            None => (false, value)
+        };
+
+        let tag = quoted.strip_prefix('"')?.strip_suffix('"')?;
+
+        if tag.contains('"') {
+            return None;
+        }
+
+        Some(Self { weak, tag: tag.to_owned() })
+    }
+
+    /// Both tags are strong and identical.
+    pub fn strong_eq(&self, other: &ETag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// The tags are identical, ignoring weakness.
+    pub fn weak_eq(&self, other: &ETag) -> bool {
+        self.tag == other.tag
+    }
+}
+
+impl fmt::Display for ETag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.weak {
+            true => write!(f, "W/\"{}\"", self.tag),
+            false => write!(f, "\"{}\"", self.tag)
+        }
+    }
+}
+
+/// Outcome of evaluating the preconditions of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precondition {
+    /// Perform the request normally.
+    Proceed,
+    /// Answer a GET or HEAD with 304.
+    NotModified,
+    /// Answer with 412.
+    Failed,
+}
+
+/// Validators of the current representation of a resource.
+#[derive(Debug, Clone, Default)]
+pub struct Validators {
+    pub etag: Option<ETag>,
+    pub last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+
+    pub fn new(etag: Option<ETag>, last_modified: Option<SystemTime>) -> Self {
+        Self {
+            etag,
+            last_modified: last_modified.map(date::truncate)
+        }
+    }
+
+    /// Evaluates `If-Match`, `If-Unmodified-Since`, `If-None-Match` and
+    /// `If-Modified-Since` in the order given by RFC 9110, section 13.2.2.
+    pub fn evaluate(&self, request: &Request) -> Precondition {
+
+        let safe = matches!(request.method, Method::Get | Method::Head);
+
+        if let Some(value) = request.header("If-Match") {
+            if !self.matches(value, ETag::strong_eq) {
+                return Precondition::Failed;
+            }
+        } else if let Some(since) = request.header("If-Unmodified-Since").and_then(date::parse) {
+            if self.last_modified.is_some_and(|modified| modified > since) {
+                return Precondition::Failed;
+            }
+        }
+
+        if let Some(value) = request.header("If-None-Match") {
+            if self.matches(value, ETag::weak_eq) {
+                return match safe {
+                    true => Precondition::NotModified,
+                    false => Precondition::Failed
+                };
+            }
+        } else if let Some(since) = request.header("If-Modified-Since").and_then(date::parse) {
+            if safe && self.last_modified.is_some_and(|modified| modified <= since) {
+                return Precondition::NotModified;
+            }
+        }
+
+        Precondition::Proceed
+    }
+
+    /// Evaluates the preconditions of `request` and returns the response that
+    /// replaces the normal one, if any.
+    ///
+    /// ```ignore
+    /// let validators = Validators::new(Some(ETag::strong(&article.revision)), None);
+    /// if let Some(response) = validators.check(&req) {
+    ///     return response;
+    /// }
+    /// ```
+    pub fn check(&self, request: &Request) -> Option<Response> {
+        match self.evaluate(request) {
+            Precondition::Proceed => None,
+            Precondition::NotModified => Some(self.apply(Response::new(StatusCode::Http304NotModified))),
+            Precondition::Failed => Some(Response::error(StatusCode::Http412PreconditionFailed))
+        }
+    }
+
+    /// Whether a `Range` field may be honored, that is `If-Range` is absent or
+    /// still matches. Only strong validators match.
+    pub fn if_range(&self, request: &Request) -> bool {
+        match request.header("If-Range") {
+            None => true,
+            Some(value) if value.trim_start().starts_with('"') || value.trim_start().starts_with("W/") => {
+                match (ETag::parse(value), &self.etag) {
+                    (Some(requested), Some(current)) => requested.strong_eq(current),
+                    _ => false
+                }
+            }
+            Some(value) => match (date::parse(value), self.last_modified) {
+                (Some(since), Some(modified)) => since == modified,
+                _ => false
+            }
+        }
+    }
+
+    /// Adds the `ETag` and `Last-Modified` fields to `response`.
+    pub fn apply(&self, mut response: Response) -> Response {
+        if let Some(etag) = &self.etag {
+            response.headers.insert("ETag", etag.to_string());
+        }
+        if let Some(modified) = self.last_modified {
+            response.headers.insert("Last-Modified", date::format(modified));
+        }
+        response
+    }
+
+    /// `If-Match`/`If-None-Match` list membership, `*` matches any current representation.
+    fn matches(&self, value: &str, eq: fn(&ETag, &ETag) -> bool) -> bool {
+        let current = match &self.etag {
+            Some(current) => current,
+            None => return value.trim() == "*"
+        };
+
+        value.trim() == "*" || split_etags(value).any(|etag| eq(&etag, current))
+    }
+}
+
+/// Splits a list of entity tags, commas inside the quotes do not separate.
+fn split_etags(value: &str) -> impl Iterator<Item = ETag> + '_ {
+    let mut rest = value;
+
+    std::iter::from_fn(move || {
+        loop {
+            rest = rest.trim_start_matches([',', ' ', '\t']);
+
+            if rest.is_empty() {
+                return None;
+            }
+
+            let start = if rest.starts_with("W/") { 2 } else { 0 };
+            let end = match rest[start..].strip_prefix('"').and_then(|tail| tail.find('"')) {
+                Some(index) => start + index + 2,
+                None => rest.find(',').unwrap_or(rest.len())
+            };
+
+            let (item, tail) = rest.split_at(end);
+            rest = tail;
+
+            if let Some(etag) = ETag::parse(item) {
+                return Some(etag);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+    use crate::srv::reader::Limits;
+
+    /// Wed, 21 Oct 2015 07:28:00 GMT
+    fn modified() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1445412480)
+    }
+
+    fn validators() -> Validators {
+        Validators::new(Some(ETag::strong("v1")), Some(modified()))
+    }
+
+    fn request(method: &str, headers: &[(&str, &str)]) -> Request {
+        let mut head = format!("{} /file HTTP/1.1\r\nHost: x\r\n", method);
+        for (name, value) in headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        Request::from(&mut head.as_bytes(), &Limits::default()).unwrap()
+    }
+
+    #[test]
+    fn if_range_compares_entity_tags() {
+        let validators = validators();
+
+        assert!(validators.if_range(&request("GET", &[])));
+        assert!(validators.if_range(&request("GET", &[("If-Range", "\"v1\"")])));
+        assert!(!validators.if_range(&request("GET", &[("If-Range", "\"v2\"")])));
+        // weak tags never match
+        assert!(!validators.if_range(&request("GET", &[("If-Range", "W/\"v1\"")])));
+        assert!(!Validators::default().if_range(&request("GET", &[("If-Range", "\"v1\"")])));
+    }
+
+    #[test]
+    fn if_range_compares_dates() {
+        let validators = validators();
+
+        assert!(validators.if_range(&request("GET", &[("If-Range", "Wed, 21 Oct 2015 07:28:00 GMT")])));
+        assert!(validators.if_range(&request("GET", &[("If-Range", "Wednesday, 21-Oct-15 07:28:00 GMT")])));
+        assert!(!validators.if_range(&request("GET", &[("If-Range", "Wed, 21 Oct 2015 07:28:01 GMT")])));
+        assert!(!validators.if_range(&request("GET", &[("If-Range", "Thu, 22 Oct 2015 07:28:00 GMT")])));
+        assert!(!validators.if_range(&request("GET", &[("If-Range", "yesterday")])));
+    }
+
+    #[test]
+    fn evaluates_entity_tag_preconditions() {
+        let validators = validators();
+
+        assert_eq!(validators.evaluate(&request("GET", &[])), Precondition::Proceed);
+        assert_eq!(validators.evaluate(&request("GET", &[("If-None-Match", "\"v0\", W/\"v1\"")])), Precondition::NotModified);
+        assert_eq!(validators.evaluate(&request("GET", &[("If-None-Match", "\"v0\"")])), Precondition::Proceed);
+        assert_eq!(validators.evaluate(&request("POST", &[("If-None-Match", "*")])), Precondition::Failed);
+        assert_eq!(validators.evaluate(&request("PUT", &[("If-Match", "\"v1\"")])), Precondition::Proceed);
+        assert_eq!(validators.evaluate(&request("PUT", &[("If-Match", "W/\"v1\"")])), Precondition::Failed);
+        assert_eq!(Validators::default().evaluate(&request("PUT", &[("If-Match", "*")])), Precondition::Proceed);
+        assert_eq!(Validators::default().evaluate(&request("PUT", &[("If-Match", "\"v1\"")])), Precondition::Failed);
+    }
+
+    #[test]
+    fn evaluates_date_preconditions() {
+        let validators = validators();
+
+        assert_eq!(validators.evaluate(&request("GET", &[("If-Modified-Since", "Wed, 21 Oct 2015 07:28:00 GMT")])), Precondition::NotModified);
+        assert_eq!(validators.evaluate(&request("GET", &[("If-Modified-Since", "Wed, 21 Oct 2015 07:27:59 GMT")])), Precondition::Proceed);
+        assert_eq!(validators.evaluate(&request("POST", &[("If-Modified-Since", "Wed, 21 Oct 2015 07:28:00 GMT")])), Precondition::Proceed);
+        assert_eq!(validators.evaluate(&request("PUT", &[("If-Unmodified-Since", "Wed, 21 Oct 2015 07:27:59 GMT")])), Precondition::Failed);
+        assert_eq!(validators.evaluate(&request("PUT", &[("If-Unmodified-Since", "Wed, 21 Oct 2015 07:28:00 GMT")])), Precondition::Proceed);
+        // an invalid date is ignored
+        assert_eq!(validators.evaluate(&request("GET", &[("If-Modified-Since", "Wed, 32 Oct 2015 07:28:00 GMT")])), Precondition::Proceed);
+    }
+
+    #[test]
+    fn entity_tags_take_precedence_over_dates() {
+        let validators = validators();
+
+        // If-None-Match replaces If-Modified-Since, If-Match replaces If-Unmodified-Since
+        let request = request("GET", &[
+            ("If-None-Match", "\"v0\""),
+            ("If-Modified-Since", "Wed, 21 Oct 2015 07:28:00 GMT"),
+        ]);
+        assert_eq!(validators.evaluate(&request), Precondition::Proceed);
+
+        let request = self::request("PUT", &[
+            ("If-Match", "\"v1\""),
+            ("If-Unmodified-Since", "Tue, 20 Oct 2015 07:28:00 GMT"),
+        ]);
+        assert_eq!(validators.evaluate(&request), Precondition::Proceed);
+    }
+}
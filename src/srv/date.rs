@@ -0,0 +1,177 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Formats `time` as an IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+///
+/// Times before the epoch are clamped to it.
+pub fn format(time: SystemTime) -> String {
+
+    let seconds = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let days = seconds / 86400;
+    let (year, month, day) = civil_from_days(days as i64);
+    let rest = seconds % 86400;
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        rest / 3600,
+        rest / 60 % 60,
+        rest % 60
+    )
+}
+
+/// Parses an IMF-fixdate, or the obsolete RFC 850 and asctime formats.
+pub fn parse(value: &str) -> Option<SystemTime> {
+
+    let parts: Vec<&str> = value.split_ascii_whitespace().collect();
+
+    let (day, month, year, time) = match parts.as_slice() {
+        // Sun, 06 Nov 1994 08:49:37 GMT
+        [_, day, month, year, time, "GMT"] => (*day, *month, digits(year, 4)?, *time),
+        // Sunday, 06-Nov-94 08:49:37 GMT
+        [_, date, time, "GMT"] => {
+            let mut date = date.split('-');
+            let (day, month, year) = (date.next()?, date.next()?, date.next()?);
+            let year = digits(year, 2)?;
+            // two digit years more than 50 years in the future are in the past
+            let year = if year < 70 { 2000 + year } else if year < 100 { 1900 + year } else { year };
+            (day, month, year, *time)
+        }
+        // Sun Nov  6 08:49:37 1994
+        [_, month, day, time, year] => (*day, *month, digits(year, 4)?, *time),
+        _ => return None
+    };
+
+    let day: u32 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|name| *name == month)? as u32 + 1;
+
+    let mut time = time.split(':');
+    let hour: u64 = time.next()?.parse().ok()?;
+    let minute: u64 = time.next()?.parse().ok()?;
+    let second: u64 = time.next()?.parse().ok()?;
+
+    if !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 || time.next().is_some() {
+        return None;
+    }
+
+    // dates before the epoch do not convert
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    let seconds = days
+        .checked_mul(86400)?
+        .checked_add(hour * 3600 + minute * 60 + second)?;
+
+    UNIX_EPOCH.checked_add(Duration::from_secs(seconds))
+}
+
+/// Parses a number written with exactly `count` ASCII digits.
+fn digits(text: &str, count: usize) -> Option<i64> {
+    match text.len() == count && text.bytes().all(|byte| byte.is_ascii_digit()) {
+        true => text.parse().ok(),
+        false => None
+    }
+}
+
+/// Truncates `time` to whole seconds, the resolution of an HTTP-date.
+pub fn truncate(time: SystemTime) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs())
+}
+
+// Howard Hinnant's days_from_civil/civil_from_days for the proleptic Gregorian calendar.
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sun, 06 Nov 1994 08:49:37 GMT
+    const EXAMPLE: u64 = 784111777;
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn formats_imf_fixdate() {
+        assert_eq!(format(at(EXAMPLE)), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(format(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(format(at(951782400)), "Tue, 29 Feb 2000 00:00:00 GMT");
+        assert_eq!(format(UNIX_EPOCH - Duration::from_secs(1)), "Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+
+    #[test]
+    fn parses_all_three_formats() {
+        assert_eq!(parse("Sun, 06 Nov 1994 08:49:37 GMT"), Some(at(EXAMPLE)));
+        assert_eq!(parse("Sunday, 06-Nov-94 08:49:37 GMT"), Some(at(EXAMPLE)));
+        assert_eq!(parse("Sun Nov  6 08:49:37 1994"), Some(at(EXAMPLE)));
+        assert_eq!(parse("Thursday, 01-Jan-70 00:00:00 GMT"), Some(UNIX_EPOCH));
+        assert_eq!(parse("Tuesday, 01-Jan-30 00:00:00 GMT"), parse("Tue, 01 Jan 2030 00:00:00 GMT"));
+    }
+
+    #[test]
+    fn round_trips() {
+        for seconds in [0, EXAMPLE, 951782400, 4102444799, 253402300799] {
+            assert_eq!(parse(&format(at(seconds))), Some(at(seconds)), "{}", seconds);
+        }
+    }
+
+    #[test]
+    fn refuses_invalid_dates() {
+        for value in [
+            "",
+            "Sun, 06 Nov 1994 08:49:37",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Sun, 32 Nov 1994 08:49:37 GMT",
+            "Sun, 00 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Nov 1994 08:60:00 GMT",
+            "Sun, 06 Nov 1994 08:49:37:00 GMT",
+            "Sun, 06 Nov 1969 08:49:37 GMT",
+            "Sun, 06 Nov 94 08:49:37 GMT",
+            "Sun, 06 Nov +994 08:49:37 GMT",
+            "Sunday, 06-Nov-1994 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 94",
+        ] {
+            assert_eq!(parse(value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn refuses_huge_years_without_overflowing() {
+        assert_eq!(parse("Sun, 06 Nov 999999999999 08:49:37 GMT"), None);
+        assert_eq!(parse("Sun Nov  6 08:49:37 99999999999999999999"), None);
+        assert_eq!(parse("Sat, 06 Nov 9999 23:59:59 GMT"), Some(at(253397548799)));
+    }
+
+    #[test]
+    fn truncates_to_seconds() {
+        assert_eq!(truncate(at(EXAMPLE) + Duration::from_millis(999)), at(EXAMPLE));
+    }
+}
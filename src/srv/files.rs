@@ -0,0 +1,581 @@
+use std::collections::hash_map::RandomState;
+use std::fs::{File, Metadata};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+use super::http::*;
+use super::compress::negotiate;
+use super::conditional::{ETag, Validators};
+use super::query::{percent_decode, percent_encode};
+use super::range::{self, Ranges};
+use super::{autoindex, Content, Request, Response, Server, RES_NOT_FOUND, RES_SERVER_ERROR};
+
+/// Suffixes of precompressed sidecar files and their content coding, in order of preference.
+pub const PRECOMPRESSED: [(&str, &str); 2] = [("br", ".br"), ("gzip", ".gz")];
+
+/// How symbolic links below the public directory are treated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    /// Refuse any path that goes through a symbolic link.
+    Deny,
+    /// Follow links as long as the target stays inside the public directory.
+    #[default]
+    WithinRoot,
+    /// Follow links wherever they point to.
+    Follow,
+}
+
+/// Answers `request` with a file from `root`.
+///
+/// Files carry `ETag` and `Last-Modified` validators and honor conditional and range requests.
+/// With [`Server::precompressed`] a `.br` or `.gz` file next to the requested one is sent
+/// instead when the client accepts that coding and the variant is not older than the file.
+///
+/// A directory is answered with its `index.html`, or a listing with [`Server::autoindex`];
+/// directory paths without the trailing slash are redirected to it first so relative
+/// links keep working.
+///
+/// With [`Server::spa`] a page navigation to a missing path gets the fallback document,
+/// while missing paths with a file extension still get 404.
+pub fn serve(server: &Server, root: &Path, request: &Request) -> Response {
+
+    let response = serve_path(server, root, request);
+
+    if response.status != StatusCode::Http404NotFound {
+        return response;
+    }
+
+    match &server.spa {
+        Some(document) if wants_document(request) => {
+            let document_path = format!("/{}", document.trim_start_matches('/'));
+
+            match resolve(root, &document_path, server.symlinks) {
+                Ok(path) if path.is_file() => serve_resource(server, root, request, &document_path, &path),
+                _ => response
+            }
+        }
+        _ => response
+    }
+}
+
+/// A GET or HEAD for an extension-less path from a client that accepts HTML, as browsers send
+/// when following a client-side route.
+fn wants_document(request: &Request) -> bool {
+
+    let last_segment = request.path.rsplit('/').next().unwrap_or_default();
+
+    let accepts_html = request.headers.get_list("Accept").any(|item| {
+        let mut params = item.split(';');
+        let media_range = params.next().unwrap_or_default().trim();
+        let refused = params.any(|param| {
+            matches!(param.split_once('='), Some((name, value))
+                if name.trim().eq_ignore_ascii_case("q") && value.trim().parse::<f32>() == Ok(0.0))
+        });
+
+        !refused && (media_range.eq_ignore_ascii_case("text/html") || media_range.eq_ignore_ascii_case("text/*"))
+    });
+
+    matches!(request.method, Method::Get | Method::Head) && accepts_html && !last_segment.contains('.')
+}
+
+fn serve_path(server: &Server, root: &Path, request: &Request) -> Response {
+
+    let resource_path = match resolve(root, &request.path, server.symlinks) {
+        Ok(path) => path,
+        Err(StatusCode::Http404NotFound) => return RES_NOT_FOUND,
+        Err(status) => return Response::error(status)
+    };
+
+    if resource_path.is_dir() {
+
+        let index_path = format!("{}/index.html", request.path.trim_end_matches('/'));
+        let index = resolve(root, &index_path, server.symlinks)
+            .ok()
+            .filter(|path| path.is_file());
+
+        if index.is_none() && !server.autoindex {
+            return RES_NOT_FOUND;
+        }
+
+        if !request.path.ends_with('/') {
+            // the raw path may start with `//`, which a browser reads as another host
+            let location = match normalize(&request.path) {
+                Ok(segments) => directory_location(&segments),
+                Err(status) => return Response::error(status)
+            };
+
+            return match request.query.is_empty() {
+                true => Response::moved_permanently(&location),
+                false => Response::moved_permanently(&format!("{}?{}", location, request.query))
+            };
+        }
+
+        return match index {
+            Some(index) => serve_resource(server, root, request, &index_path, &index),
+            None => match autoindex::render(server, root, request, &resource_path) {
+                Ok(response) => response,
+                Err(_) => RES_SERVER_ERROR
+            }
+        };
+    }
+
+    if !resource_path.is_file() {
+        return RES_NOT_FOUND;
+    }
+
+    serve_resource(server, root, request, &request.path, &resource_path)
+}
+
+/// Sends the regular file `resource_path` found at `request_path`, or a precompressed variant of it.
+fn serve_resource(server: &Server, root: &Path, request: &Request, request_path: &str, resource_path: &Path) -> Response {
+
+    let modified = |path: &Path| path.metadata().and_then(|metadata| metadata.modified()).ok();
+    let original_modified = modified(resource_path);
+
+    // precompressed variants are looked up like any other path, so they obey the same rules
+    let variants: Vec<(PathBuf, &str)> = match server.precompressed {
+        true => PRECOMPRESSED
+            .iter()
+            .filter_map(|(coding, suffix)| {
+                resolve(root, &format!("{}{}", request_path, suffix), server.symlinks)
+                    .ok()
+                    .filter(|path| path.is_file())
+                    // a variant older than the file was left behind by an earlier build
+                    .filter(|path| modified(path) >= original_modified)
+                    .map(|path| (path, *coding))
+            })
+            .collect(),
+        false => vec![]
+    };
+
+    let selected = request.header("Accept-Encoding").and_then(|value| {
+        let available: Vec<(usize, &str)> = variants
+            .iter()
+            .enumerate()
+            .map(|(index, (_, coding))| (index, *coding))
+            .collect();
+        negotiate(value, &available)
+    });
+
+    // the type always comes from the original file, not from the `.gz` or `.br` suffix
+    let content_type = ContentType::guess(resource_path);
+
+    let response = match selected {
+        Some(index) => {
+            let (path, coding) = &variants[index];
+            serve_file(server, request, path, content_type)
+                .map(|response| response.with_header("Content-Encoding", coding))
+        }
+        None => serve_file(server, request, resource_path, content_type)
+    };
+
+    match response {
+        Ok(response) if !variants.is_empty() => response.with_header("Vary", "Accept-Encoding"),
+        Ok(response) => response,
+        Err(_) => RES_SERVER_ERROR
+    }
+}
+
+/// Sends the file at `path`, or the parts of it selected by a `Range` field.
+fn serve_file(server: &Server, request: &Request, path: &Path, content_type: ContentType) -> io::Result<Response> {
+
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let length = metadata.len();
+    let validators = file_validators(&metadata, server.weak_etags);
+
+    if let Some(response) = validators.check(request) {
+        return Ok(response.with_header("Accept-Ranges", "bytes"));
+    }
+
+    let ranges = match request.header("Range") {
+        Some(value) if request.method == Method::Get && validators.if_range(request) => {
+            range::parse(value, length)
+        }
+        _ => Ranges::Full
+    };
+
+    let response = match ranges {
+        Ranges::Full => Response::new(StatusCode::Http200Ok)
+            .with_body(Content::File(file, length), content_type),
+        Ranges::Unsatisfiable => Response::error(StatusCode::Http416RangeNotSatisfiable)
+            .with_header("Content-Range", &format!("bytes */{}", length)),
+        Ranges::Partial(ranges) if ranges.len() == 1 => {
+            let (first, last) = ranges[0];
+            file.seek(SeekFrom::Start(first))?;
+
+            Response::new(StatusCode::Http206PartialContent)
+                .with_header("Content-Range", &format!("bytes {}-{}/{}", first, last, length))
+                .with_body(Content::File(file, last - first + 1), content_type)
+        }
+        Ranges::Partial(ranges) => byteranges(path, &ranges, length, content_type)?
+    };
+
+    Ok(validators.apply(response).with_header("Accept-Ranges", "bytes"))
+}
+
+/// Builds a `multipart/byteranges` response streaming every range from its own file handle.
+fn byteranges(path: &Path, ranges: &[(u64, u64)], length: u64, content_type: ContentType) -> io::Result<Response> {
+
+    let boundary = format!("{:016x}", RandomState::new().build_hasher().finish());
+
+    let mut body: Box<dyn Read + Send> = Box::new(io::empty());
+    let mut total = 0;
+
+    for &(first, last) in ranges {
+
+        let part_head = format!(
+            "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+            boundary, content_type.as_str(), first, last, length
+        );
+
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(first))?;
+
+        total += part_head.len() as u64 + (last - first + 1);
+        body = Box::new(body.chain(Cursor::new(part_head)).chain(file.take(last - first + 1)));
+    }
+
+    let tail = format!("\r\n--{}--\r\n", boundary);
+    total += tail.len() as u64;
+    body = Box::new(body.chain(Cursor::new(tail)));
+
+    Ok(Response::new(StatusCode::Http206PartialContent)
+        .with_header("Content-Type", &format!("multipart/byteranges; boundary={}", boundary))
+        .with_body(Content::Stream(body, Some(total)), content_type))
+}
+
+/// Validators derived from the size and modification time of a file.
+fn file_validators(metadata: &Metadata, weak: bool) -> Validators {
+
+    let modified = metadata.modified().ok();
+    let stamp = modified
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .unwrap_or_default();
+
+    let tag = format!("{:x}-{:x}", metadata.len(), stamp.as_nanos());
+    let etag = match weak {
+        true => ETag::weak(&tag),
+        false => ETag::strong(&tag)
+    };
+
+    Validators::new(Some(etag), modified)
+}
+
+/// Maps a request path onto an existing file system path below `root`.
+///
+/// The path is percent-decoded and `.`/`..` segments are resolved before it touches
+/// the file system. NUL bytes, encoded or backslash separators and attempts to climb
+/// above `root` are refused with 403, as are symbolic links not allowed by `symlinks`.
+/// Paths that do not exist give 404.
+pub fn resolve(root: &Path, request_path: &str, symlinks: SymlinkPolicy) -> Result<PathBuf, StatusCode> {
+
+    let segments = normalize(request_path)?;
+    let mut path = root.to_path_buf();
+
+    for segment in &segments {
+        // a segment such as `C:` could still turn into a prefix or root component
+        match Path::new(segment).components().next() {
+            Some(Component::Normal(_)) => path.push(segment),
+            _ => return Err(StatusCode::Http403Forbidden)
+        }
+
+        if symlinks == SymlinkPolicy::Deny {
+            match path.symlink_metadata() {
+                Ok(metadata) if metadata.file_type().is_symlink() => {
+                    return Err(StatusCode::Http403Forbidden);
+                }
+                Ok(_) => (),
+                Err(_) => return Err(StatusCode::Http404NotFound)
+            }
+        }
+    }
+
+    let canonical = path.canonicalize().map_err(|_| StatusCode::Http404NotFound)?;
+
+    if symlinks != SymlinkPolicy::Follow {
+        let canonical_root = root.canonicalize().map_err(|_| StatusCode::Http404NotFound)?;
+
+        if !canonical.starts_with(canonical_root) {
+            return Err(StatusCode::Http403Forbidden);
+        }
+    }
+
+    Ok(path)
+}
+
+/// Percent-decodes a request path and resolves its `.`/`..` segments.
+///
+/// Empty segments are dropped, so the result never names another host when joined
+/// back into a path. Segments holding a NUL byte or a separator and paths climbing
+/// above the root are refused with 403, undecodable ones with 400.
+pub fn normalize(request_path: &str) -> Result<Vec<String>, StatusCode> {
+
+    let relative = match request_path.strip_prefix('/') {
+        Some(relative) => relative,
+        None => return Err(StatusCode::Http400BadRequest)
+    };
+
+    let mut segments: Vec<String> = vec![];
+
+    // splitting before decoding keeps %2F inside a segment where it is caught below
+    for raw in relative.split('/') {
+
+        let decoded = percent_decode(raw).ok_or(StatusCode::Http400BadRequest)?;
+        let segment = String::from_utf8(decoded).map_err(|_| StatusCode::Http400BadRequest)?;
+
+        if segment.contains(['\0', '/', '\\']) || cfg!(windows) && segment.contains(':') {
+            return Err(StatusCode::Http403Forbidden);
+        }
+
+        match segment.as_str() {
+            "" | "." => (),
+            ".." => {
+                if segments.pop().is_none() {
+                    return Err(StatusCode::Http403Forbidden);
+                }
+            }
+            _ => segments.push(segment)
+        }
+    }
+
+    Ok(segments)
+}
+
+/// Absolute path of the directory made of `segments`, with a trailing slash.
+fn directory_location(segments: &[String]) -> String {
+    segments.iter().fold(String::from("/"), |location, segment| location + &percent_encode(segment) + "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Creates `<tmp>/<name>/public/index.html`, `<tmp>/<name>/public/docs/a.txt`
+    /// and `<tmp>/<name>/secret.txt` and returns the public directory.
+    fn fixture(name: &str) -> PathBuf {
+        let base = std::env::temp_dir().join(format!("httpie-files-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        fs::create_dir_all(base.join("public/docs")).unwrap();
+        fs::write(base.join("public/index.html"), "index").unwrap();
+        fs::write(base.join("public/docs/a.txt"), "a").unwrap();
+        fs::write(base.join("secret.txt"), "secret").unwrap();
+        base.join("public")
+    }
+
+    #[test]
+    fn resolves_plain_and_normalized_paths() {
+        let root = fixture("plain");
+        let policy = SymlinkPolicy::WithinRoot;
+
+        assert_eq!(resolve(&root, "/index.html", policy), Ok(root.join("index.html")));
+        assert_eq!(resolve(&root, "/docs/./a.txt", policy), Ok(root.join("docs/a.txt")));
+        assert_eq!(resolve(&root, "//docs//a.txt", policy), Ok(root.join("docs/a.txt")));
+        assert_eq!(resolve(&root, "/docs/../index.html", policy), Ok(root.join("index.html")));
+        assert_eq!(resolve(&root, "/%64ocs/a.txt", policy), Ok(root.join("docs/a.txt")));
+        assert_eq!(resolve(&root, "/missing.txt", policy), Err(StatusCode::Http404NotFound));
+    }
+
+    #[test]
+    fn refuses_traversal_attempts() {
+        let root = fixture("traversal");
+        let policy = SymlinkPolicy::Follow;
+
+        for attack in [
+            "/../secret.txt",
+            "/../../../../etc/passwd",
+            "/docs/../../secret.txt",
+            "/%2e%2e/secret.txt",
+            "/%2E%2E/%2E%2E/etc/passwd",
+            "/.%2e/secret.txt",
+            "/..%2fsecret.txt",
+            "/..%2Fsecret.txt",
+            "/docs%2f..%2f..%2fsecret.txt",
+            "/..%5csecret.txt",
+            "/..\\secret.txt",
+            "/index.html%00.txt",
+            "/%00",
+        ] {
+            assert_eq!(resolve(&root, attack, policy), Err(StatusCode::Http403Forbidden), "{}", attack);
+        }
+    }
+
+    #[test]
+    fn refuses_malformed_paths() {
+        let root = fixture("malformed");
+        let policy = SymlinkPolicy::WithinRoot;
+
+        assert_eq!(resolve(&root, "index.html", policy), Err(StatusCode::Http400BadRequest));
+        assert_eq!(resolve(&root, "/%zz", policy), Err(StatusCode::Http400BadRequest));
+        assert_eq!(resolve(&root, "/%2", policy), Err(StatusCode::Http400BadRequest));
+        assert_eq!(resolve(&root, "/%ff%fe", policy), Err(StatusCode::Http400BadRequest));
+    }
+
+    #[test]
+    fn redirects_directories_within_the_server() {
+        let location = |path: &str| normalize(path).map(|segments| directory_location(&segments));
+
+        assert_eq!(location("/docs"), Ok("/docs/".to_owned()));
+        assert_eq!(location("/a b/./c"), Ok("/a%20b/c/".to_owned()));
+        assert_eq!(location("//attacker.example/.."), Ok("/".to_owned()));
+        assert_eq!(location("//attacker.example/docs"), Ok("/attacker.example/docs/".to_owned()));
+        assert_eq!(location("/%2F%2Fattacker.example"), Err(StatusCode::Http403Forbidden));
+    }
+
+    /// Status, body and the value of the header `name` of a response.
+    fn sent(response: Response, name: &str) -> (StatusCode, String, Option<String>) {
+        let header = response.headers.get(name).map(str::to_owned);
+        let body = match response.body {
+            Content::File(file, length) => {
+                let mut body = String::new();
+                file.take(length).read_to_string(&mut body).unwrap();
+                body
+            }
+            body => String::from_utf8_lossy(body.as_bytes()).into_owned()
+        };
+        (response.status, body, header)
+    }
+
+    fn encoded(server: &Server, root: &Path, accept_encoding: &str, range: Option<&str>) -> Response {
+        let range = range.map(|value| format!("Range: {}\r\n", value)).unwrap_or_default();
+        let head = format!("GET /app.js HTTP/1.1\r\nAccept-Encoding: {}\r\n{}\r\n", accept_encoding, range);
+        let request = Request::from(&mut head.as_bytes(), &super::super::Limits::default()).unwrap();
+        serve(server, root, &request)
+    }
+
+    #[test]
+    fn picks_precompressed_variants() {
+        let root = fixture("precompressed");
+        fs::write(root.join("app.js"), "plain").unwrap();
+        fs::write(root.join("app.js.gz"), "gzip").unwrap();
+        fs::write(root.join("app.js.br"), "brotli").unwrap();
+
+        let server = Server::new().precompressed(true);
+        let coding = |accept_encoding| sent(encoded(&server, &root, accept_encoding, None), "Content-Encoding");
+
+        assert_eq!(coding("gzip, br"), (StatusCode::Http200Ok, "brotli".to_owned(), Some("br".to_owned())));
+        assert_eq!(coding("br;q=0.5, gzip"), (StatusCode::Http200Ok, "gzip".to_owned(), Some("gzip".to_owned())));
+        assert_eq!(coding("gzip;q=0.9, br;q=0.1"), (StatusCode::Http200Ok, "gzip".to_owned(), Some("gzip".to_owned())));
+        assert_eq!(coding("br;q=0, gzip;q=0"), (StatusCode::Http200Ok, "plain".to_owned(), None));
+        assert_eq!(coding("identity"), (StatusCode::Http200Ok, "plain".to_owned(), None));
+
+        // the type is the one of the original file
+        assert_eq!(encoded(&server, &root, "gzip", None).content_type, ContentType::guess(Path::new("app.js")));
+
+        // whichever variant is sent, the answer depends on Accept-Encoding
+        for accept_encoding in ["br", "identity"] {
+            assert_eq!(sent(encoded(&server, &root, accept_encoding, None), "Vary").2.as_deref(), Some("Accept-Encoding"));
+        }
+        assert_eq!(sent(encoded(&Server::new(), &root, "br", None), "Vary").2, None);
+    }
+
+    #[test]
+    fn skips_stale_variants() {
+        let root = fixture("stale");
+        let now = std::time::SystemTime::now();
+        let write = |name: &str, age: u64| {
+            fs::write(root.join(name), name).unwrap();
+            let file = File::options().write(true).open(root.join(name)).unwrap();
+            file.set_modified(now - std::time::Duration::from_secs(age)).unwrap();
+        };
+        write("app.js", 60);
+        write("app.js.gz", 120);
+        write("app.js.br", 60);
+
+        let server = Server::new().precompressed(true);
+        let coding = |accept_encoding| sent(encoded(&server, &root, accept_encoding, None), "Content-Encoding").2;
+
+        // older than the file it stands for
+        assert_eq!(coding("gzip"), None);
+        // as old as the file, written by the same build
+        assert_eq!(coding("br"), Some("br".to_owned()));
+
+        write("app.js.gz", 0);
+        assert_eq!(coding("gzip"), Some("gzip".to_owned()));
+    }
+
+    #[test]
+    fn serves_ranges_of_precompressed_variants() {
+        let root = fixture("ranges");
+        fs::write(root.join("app.js"), "plain text").unwrap();
+        fs::write(root.join("app.js.gz"), "0123456789").unwrap();
+
+        let server = Server::new().precompressed(true);
+        let response = encoded(&server, &root, "gzip", Some("bytes=2-4"));
+        assert_eq!(response.headers.get("Content-Encoding"), Some("gzip"));
+
+        // positions count in the encoded bytes
+        let (status, body, range) = sent(response, "Content-Range");
+        assert_eq!(status, StatusCode::Http206PartialContent);
+        assert_eq!(body, "234");
+        assert_eq!(range.as_deref(), Some("bytes 2-4/10"));
+
+        let (_, body, range) = sent(encoded(&server, &root, "identity", Some("bytes=-4")), "Content-Range");
+        assert_eq!((body.as_str(), range.as_deref()), ("text", Some("bytes 6-9/10")));
+    }
+
+    fn request(method: &str, path: &str, accept: Option<&str>) -> Request {
+        let accept = accept.map(|value| format!("Accept: {}\r\n", value)).unwrap_or_default();
+        let head = format!("{} {} HTTP/1.1\r\n{}\r\n", method, path, accept);
+        Request::from(&mut head.as_bytes(), &super::super::Limits::default()).unwrap()
+    }
+
+    #[test]
+    fn recognizes_page_navigations() {
+        let browser = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+        let wants = |method, path, accept| wants_document(&request(method, path, accept));
+
+        assert!(wants("GET", "/app/settings", Some(browser)));
+        assert!(wants("HEAD", "/app/settings/", Some(browser)));
+        assert!(wants("GET", "/", Some("TEXT/HTML")));
+        assert!(wants("GET", "/app", Some("application/json, text/*;q=0.5")));
+        assert!(wants("GET", "/app", Some("text/html;q=0.001")));
+        // a dot in an earlier segment is not an extension
+        assert!(wants("GET", "/v1.2/page", Some(browser)));
+
+        // HTML refused or not asked for by name, as fetch and curl send
+        assert!(!wants("GET", "/app", Some("text/html;q=0")));
+        assert!(!wants("GET", "/app", Some("text/html; Q=0.0, application/json")));
+        assert!(!wants("GET", "/app", Some("*/*")));
+        assert!(!wants("GET", "/app", Some("application/json")));
+        assert!(!wants("GET", "/app", None));
+
+        // assets and other methods
+        assert!(!wants("GET", "/app.js", Some(browser)));
+        assert!(!wants("GET", "/static/logo.v2.png", Some(browser)));
+        assert!(!wants("GET", "/.well-known", Some(browser)));
+        assert!(!wants("POST", "/app", Some(browser)));
+    }
+
+    #[test]
+    fn falls_back_to_the_document() {
+        let root = fixture("spa");
+        let server = Server::new().spa("index.html");
+        let browser = Some("text/html");
+
+        let response = serve(&server, &root, &request("GET", "/app/settings", browser));
+        assert_eq!(response.status, StatusCode::Http200Ok);
+        assert_eq!(response.content_type, ContentType::TextHtml);
+
+        assert_eq!(serve(&server, &root, &request("GET", "/app.js", browser)).status, StatusCode::Http404NotFound);
+        assert_eq!(serve(&server, &root, &request("GET", "/app/settings", Some("*/*"))).status, StatusCode::Http404NotFound);
+        assert_eq!(serve(&Server::new(), &root, &request("GET", "/app", browser)).status, StatusCode::Http404NotFound);
+        assert_eq!(serve(&server.spa("missing.html"), &root, &request("GET", "/app", browser)).status, StatusCode::Http404NotFound);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn applies_symlink_policy() {
+        let root = fixture("symlinks");
+        std::os::unix::fs::symlink(root.join("../secret.txt"), root.join("escape.txt")).unwrap();
+        std::os::unix::fs::symlink(root.join("docs/a.txt"), root.join("inside.txt")).unwrap();
+
+        assert_eq!(resolve(&root, "/escape.txt", SymlinkPolicy::WithinRoot), Err(StatusCode::Http403Forbidden));
+        assert_eq!(resolve(&root, "/inside.txt", SymlinkPolicy::WithinRoot), Ok(root.join("inside.txt")));
+        assert_eq!(resolve(&root, "/escape.txt", SymlinkPolicy::Follow), Ok(root.join("escape.txt")));
+        assert_eq!(resolve(&root, "/inside.txt", SymlinkPolicy::Deny), Err(StatusCode::Http403Forbidden));
+        assert_eq!(resolve(&root, "/docs/a.txt", SymlinkPolicy::Deny), Ok(root.join("docs/a.txt")));
+    }
+}
@@ -0,0 +1,545 @@
+use std::fmt;
+use std::io::{self, Read};
+
+use super::http::{ContentType, Headers, StatusCode};
+use super::query::Query;
+use super::reader::{parse_fields, DEFAULT_MAX_HEADER_SIZE, DEFAULT_MAX_UPLOAD_SIZE};
+use super::Request;
+
+/// Default upper bound for the number of parts in a multipart body.
+pub const DEFAULT_MAX_PARTS: usize = 64;
+
+/// Bytes requested from the body at once while looking for a delimiter.
+const READ_SIZE: usize = 16 * 1024;
+
+/// Limits applied while reading a multipart body.
+#[derive(Debug, Clone, Copy)]
+pub struct MultipartLimits {
+    pub max_parts: usize,
+    pub max_part_header_size: usize,
+    pub max_part_size: u64,
+}
+
+impl Default for MultipartLimits {
+    fn default() -> Self {
+        Self {
+            max_parts: DEFAULT_MAX_PARTS,
+            max_part_header_size: DEFAULT_MAX_HEADER_SIZE,
+            max_part_size: DEFAULT_MAX_UPLOAD_SIZE as u64,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FormError {
+    /// The body is not of the form type asked for.
+    UnsupportedMediaType,
+    /// The body does not follow its media type.
+    Malformed,
+    /// The body has more or larger parts than allowed.
+    TooLarge,
+    Io(io::Error),
+}
+
+impl FormError {
+    /// Status code to answer the client with.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            FormError::UnsupportedMediaType => StatusCode::Http415UnsupportedMediaType,
+            FormError::TooLarge => StatusCode::Http413PayloadToolarge,
+            FormError::Io(err) if err.kind() == io::ErrorKind::TimedOut => StatusCode::Http408RequestTimeout,
+            FormError::Malformed | FormError::Io(_) => StatusCode::Http400BadRequest,
+        }
+    }
+}
+
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormError::UnsupportedMediaType => write!(f, "unsupported form media type"),
+            FormError::Malformed => write!(f, "malformed form body"),
+            FormError::TooLarge => write!(f, "form body too large"),
+            FormError::Io(err) => write!(f, "error reading the form body: {}", err)
+        }
+    }
+}
+
+impl std::error::Error for FormError {}
+
+impl From<io::Error> for FormError {
+    fn from(err: io::Error) -> Self {
+        // errors raised by a part come back through its Read implementation
+        match err.get_ref().and_then(|inner| inner.downcast_ref::<FormError>()) {
+            Some(FormError::TooLarge) => FormError::TooLarge,
+            Some(_) => FormError::Malformed,
+            None if err.kind() == io::ErrorKind::UnexpectedEof => FormError::Malformed,
+            None => FormError::Io(err)
+        }
+    }
+}
+
+impl Request {
+
+    /// Fields of an `application/x-www-form-urlencoded` body.
+    pub fn form(&self) -> Result<Query, FormError> {
+
+        if self.content_type != ContentType::ApplicationFormUrlencoded {
+            return Err(FormError::UnsupportedMediaType);
+        }
+
+        Ok(Query::parse(&String::from_utf8_lossy(&self.content)))
+    }
+
+    /// Parts of a `multipart/form-data` body with the default limits.
+    pub fn multipart(&mut self) -> Result<Multipart<'_>, FormError> {
+        self.multipart_with(MultipartLimits::default())
+    }
+
+    /// Parts of a `multipart/form-data` body.
+    ///
+    /// Uploads with a known length are read from the connection while the parts are
+    /// read, others were buffered with the request.
+    pub fn multipart_with(&mut self, limits: MultipartLimits) -> Result<Multipart<'_>, FormError> {
+
+        if self.content_type != ContentType::MultipartFormData {
+            return Err(FormError::UnsupportedMediaType);
+        }
+
+        let boundary = self
+            .header("Content-Type")
+            .and_then(|value| parameter(value, "boundary"))
+            .filter(|boundary| (1..=70).contains(&boundary.len()))
+            .ok_or(FormError::Malformed)?;
+
+        let reader: Box<dyn Read + '_> = match self.body.take() {
+            Some(body) => Box::new(body),
+            None => Box::new(self.content.as_slice())
+        };
+
+        Ok(Multipart::new(reader, &boundary, limits))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Inside the preamble or the content of a part.
+    Content,
+    /// Right after a delimiter, before the rest of its line.
+    Delimiter,
+    Done,
+}
+
+/// Reader over the parts of a `multipart/form-data` body, one part at a time.
+///
+/// ```ignore
+/// let mut multipart = request.multipart()?;
+///
+/// while let Some(mut part) = multipart.next_part()? {
+///     match part.filename() {
+///         Some(filename) => io::copy(&mut part, &mut File::create(upload_path(&filename))?)?,
+///         None => fields.push((part.name().unwrap_or_default(), part.text()?)),
+///     };
+/// }
+/// ```
+pub struct Multipart<'a> {
+    reader: Box<dyn Read + 'a>,
+    delimiter: Vec<u8>,
+    buffer: Vec<u8>,
+    state: State,
+    limits: MultipartLimits,
+    parts: usize,
+}
+
+impl<'a> Multipart<'a> {
+
+    pub fn new(reader: Box<dyn Read + 'a>, boundary: &str, limits: MultipartLimits) -> Self {
+        Self {
+            reader,
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            // lets the first delimiter match without the line break before it
+            buffer: b"\r\n".to_vec(),
+            state: State::Content,
+            limits,
+            parts: 0,
+        }
+    }
+
+    /// Skips what is left of the current part and reads the header fields of the next one.
+    pub fn next_part(&mut self) -> Result<Option<Part<'_, 'a>>, FormError> {
+
+        let mut skipped = vec![0; READ_SIZE];
+
+        while self.state == State::Content {
+            self.read_content(&mut skipped)?;
+        }
+
+        if self.state == State::Done {
+            return Ok(None);
+        }
+
+        // the rest of the delimiter line is `--` after the last part, else blank
+        let line = self.read_line(self.limits.max_part_header_size)?;
+
+        if line.starts_with(b"--") {
+            self.state = State::Done;
+            return Ok(None);
+        }
+        if !line.iter().all(|byte| byte.is_ascii_whitespace()) {
+            return Err(FormError::Malformed);
+        }
+
+        self.parts += 1;
+
+        if self.parts > self.limits.max_parts {
+            return Err(FormError::TooLarge);
+        }
+
+        let mut block = vec![];
+
+        loop {
+            let limit = self.limits.max_part_header_size.saturating_sub(block.len());
+            let line = self.read_line(limit)?;
+
+            if line.trim_ascii().is_empty() {
+                break;
+            }
+            block.extend_from_slice(&line);
+        }
+
+        let mut headers = Headers::new();
+
+        if !parse_fields(&String::from_utf8_lossy(&block), &mut headers) {
+            return Err(FormError::Malformed);
+        }
+
+        self.state = State::Content;
+
+        Ok(Some(Part { multipart: self, headers, read: 0 }))
+    }
+
+    /// Reads content up to the next delimiter, returns 0 once it is reached.
+    fn read_content(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+
+        loop {
+            if self.state != State::Content {
+                return Ok(0);
+            }
+
+            let found = self.buffer
+                .windows(self.delimiter.len())
+                .position(|window| window == self.delimiter.as_slice());
+
+            // bytes that can not be the start of a delimiter
+            let available = match found {
+                Some(0) => {
+                    self.buffer.drain(..self.delimiter.len());
+                    self.state = State::Delimiter;
+                    return Ok(0);
+                }
+                Some(position) => position,
+                None => self.buffer.len().saturating_sub(self.delimiter.len() - 1)
+            };
+
+            if available > 0 {
+                let count = available.min(buf.len());
+                buf[..count].copy_from_slice(&self.buffer[..count]);
+                self.buffer.drain(..count);
+                return Ok(count);
+            }
+
+            if self.fill()? == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, FormError::Malformed));
+            }
+        }
+    }
+
+    /// Takes one line including its terminator off the body.
+    fn read_line(&mut self, limit: usize) -> Result<Vec<u8>, FormError> {
+
+        loop {
+            if let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+                if end >= limit {
+                    return Err(FormError::TooLarge);
+                }
+                return Ok(self.buffer.drain(..=end).collect());
+            }
+
+            if self.buffer.len() >= limit {
+                return Err(FormError::TooLarge);
+            }
+            if self.fill()? == 0 {
+                return Err(FormError::Malformed);
+            }
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<usize> {
+        let start = self.buffer.len();
+        self.buffer.resize(start + READ_SIZE, 0);
+
+        let read = self.reader.read(&mut self.buffer[start..]);
+        self.buffer.truncate(start + *read.as_ref().unwrap_or(&0));
+        read
+    }
+}
+
+/// One part of a multipart body, its content is read through [`Read`].
+pub struct Part<'m, 'a> {
+    multipart: &'m mut Multipart<'a>,
+    pub headers: Headers,
+    read: u64,
+}
+
+impl Part<'_, '_> {
+
+    /// Field name from `Content-Disposition`.
+    pub fn name(&self) -> Option<String> {
+        self.headers.get("Content-Disposition").and_then(|value| parameter(value, "name"))
+    }
+
+    /// Name of the uploaded file, for file fields.
+    ///
+    /// This is whatever the client sent, not a safe path to write to.
+    pub fn filename(&self) -> Option<String> {
+        self.headers.get("Content-Disposition").and_then(|value| parameter(value, "filename"))
+    }
+
+    /// Media type of the content, `text/plain` unless given.
+    pub fn content_type(&self) -> ContentType {
+        match self.headers.get("Content-Type") {
+            Some(value) => ContentType::from_header(value),
+            None => ContentType::TextPlain
+        }
+    }
+
+    /// Reads the rest of the content.
+    pub fn bytes(&mut self) -> Result<Vec<u8>, FormError> {
+        let mut content = vec![];
+        self.read_to_end(&mut content)?;
+        Ok(content)
+    }
+
+    /// Reads the rest of the content as text, invalid UTF-8 is replaced.
+    pub fn text(&mut self) -> Result<String, FormError> {
+        Ok(String::from_utf8_lossy(&self.bytes()?).into_owned())
+    }
+}
+
+impl Read for Part<'_, '_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+
+        let read = self.multipart.read_content(buf)?;
+        self.read += read as u64;
+
+        if self.read > self.multipart.limits.max_part_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, FormError::TooLarge));
+        }
+
+        Ok(read)
+    }
+}
+
+/// Value of the `name` parameter in a header value like `form-data; name="a"`.
+///
+/// Items without a value, like `foo` in `form-data; foo; name="a"`, are skipped.
+fn parameter(value: &str, name: &str) -> Option<String> {
+
+    // the first item is the media type or the disposition type
+    for item in split_unquoted(value, ';').into_iter().skip(1) {
+        let (key, raw) = match item.split_once('=') {
+            Some(pair) => pair,
+            None => continue
+        };
+
+        if key.trim().eq_ignore_ascii_case(name) {
+            return Some(unquote(raw.trim()));
+        }
+    }
+
+    None
+}
+
+/// Splits `value` on `separator`, except inside quoted strings.
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+
+    let mut items = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (index, ch) in value.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ch if ch == separator && !quoted => {
+                items.push(&value[start..index]);
+                start = index + ch.len_utf8();
+            }
+            _ => ()
+        }
+    }
+
+    items.push(&value[start..]);
+    items
+}
+
+/// Contents of a quoted string with its escapes resolved, other values as they are.
+fn unquote(value: &str) -> String {
+
+    let quoted = match value.strip_prefix('"') {
+        Some(quoted) => quoted,
+        None => return value.to_owned()
+    };
+
+    let mut parsed = String::new();
+    let mut chars = quoted.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => parsed.extend(chars.next()),
+            '"' => break,
+            ch => parsed.push(ch)
+        }
+    }
+
+    parsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::srv::reader::Limits;
+
+    const BODY: &str = "preamble\r\n\
+        --B\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        hello\r\n\
+        --B\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"a \\\"b\\\".txt\"\r\n\
+        Content-Type: text/html\r\n\
+        \r\n\
+        line\r\n-B\r\n--\r\n\
+        --B--\r\n\
+        epilogue";
+
+    /// Hands out one byte per read, so every delimiter straddles reads.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let count = buf.len().min(self.0.len()).min(1);
+            self.0.read(&mut buf[..count])
+        }
+    }
+
+    /// Name, filename, content type and text of a part.
+    type Summary = (Option<String>, Option<String>, ContentType, String);
+
+    fn parts(multipart: &mut Multipart) -> Result<Vec<Summary>, FormError> {
+        let mut parts = vec![];
+        while let Some(mut part) = multipart.next_part()? {
+            parts.push((part.name(), part.filename(), part.content_type(), part.text()?));
+        }
+        Ok(parts)
+    }
+
+    fn multipart(body: &str, limits: MultipartLimits) -> Multipart<'_> {
+        Multipart::new(Box::new(body.as_bytes()), "B", limits)
+    }
+
+    fn request(head: &str, body: &str) -> Request {
+        let message = format!("POST / HTTP/1.1\r\nHost: x\r\n{}\r\nContent-Length: {}\r\n\r\n{}", head, body.len(), body);
+        Request::from(&mut message.as_bytes(), &Limits::default()).unwrap()
+    }
+
+    #[test]
+    fn reads_fields_and_files() {
+        let expected = vec![
+            (Some("title".to_owned()), None, ContentType::TextPlain, "hello".to_owned()),
+            (Some("file".to_owned()), Some("a \"b\".txt".to_owned()), ContentType::TextHtml, "line\r\n-B\r\n--".to_owned()),
+        ];
+
+        assert_eq!(parts(&mut multipart(BODY, MultipartLimits::default())).unwrap(), expected);
+
+        let mut trickled = Multipart::new(Box::new(Trickle(BODY.as_bytes())), "B", MultipartLimits::default());
+        assert_eq!(parts(&mut trickled).unwrap(), expected);
+    }
+
+    #[test]
+    fn skips_unread_content() {
+        let mut multipart = multipart(BODY, MultipartLimits::default());
+
+        assert_eq!(multipart.next_part().unwrap().unwrap().name().as_deref(), Some("title"));
+        let mut file = multipart.next_part().unwrap().unwrap();
+        assert_eq!(file.name().as_deref(), Some("file"));
+
+        let mut start = [0; 2];
+        file.read_exact(&mut start).unwrap();
+        assert_eq!(&start, b"li");
+
+        assert!(multipart.next_part().unwrap().is_none());
+        assert!(multipart.next_part().unwrap().is_none());
+    }
+
+    #[test]
+    fn reads_an_empty_body() {
+        assert!(multipart("--B--\r\n", MultipartLimits::default()).next_part().unwrap().is_none());
+    }
+
+    #[test]
+    fn refuses_malformed_bodies() {
+        for body in [
+            "",
+            "no delimiter at all",
+            "--B\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nunterminated",
+            "--B\r\nnot a header\r\n\r\nx\r\n--B--\r\n",
+            "--Bogus\r\n\r\nx\r\n--B--\r\n",
+        ] {
+            assert!(matches!(parts(&mut multipart(body, MultipartLimits::default())), Err(FormError::Malformed)), "{:?}", body);
+        }
+    }
+
+    #[test]
+    fn applies_limits() {
+        let limits = MultipartLimits { max_parts: 1, ..MultipartLimits::default() };
+        assert!(matches!(parts(&mut multipart(BODY, limits)), Err(FormError::TooLarge)));
+
+        let limits = MultipartLimits { max_part_size: 5, ..MultipartLimits::default() };
+        assert!(matches!(parts(&mut multipart(BODY, limits)), Err(FormError::TooLarge)));
+
+        let limits = MultipartLimits { max_part_header_size: 32, ..MultipartLimits::default() };
+        assert!(matches!(parts(&mut multipart(BODY, limits)), Err(FormError::TooLarge)));
+    }
+
+    #[test]
+    fn finds_header_parameters() {
+        assert_eq!(parameter("form-data; name=a; filename=\"x;y.txt\"", "filename").as_deref(), Some("x;y.txt"));
+        assert_eq!(parameter("form-data; name=\"a\\\\b\"", "name").as_deref(), Some("a\\b"));
+        assert_eq!(parameter("multipart/form-data; BOUNDARY = abc ", "boundary").as_deref(), Some("abc"));
+        assert_eq!(parameter("form-data; filename=\"x\"", "name"), None);
+        assert_eq!(parameter("form-data", "name"), None);
+
+        // items without a value and separators inside quotes
+        assert_eq!(parameter("form-data; foo; name=\"a\"", "name").as_deref(), Some("a"));
+        assert_eq!(parameter("form-data; ; name=a;", "name").as_deref(), Some("a"));
+        assert_eq!(parameter("form-data; x=\"name=b; c\"; name=\"a\"", "name").as_deref(), Some("a"));
+        assert_eq!(parameter("form-data; x=\"\\\"; name=b\"; name=a", "name").as_deref(), Some("a"));
+        assert_eq!(parameter("form-data; filename=\"a=b.txt\"; name=c", "filename").as_deref(), Some("a=b.txt"));
+        assert_eq!(parameter("form-data; foo", "name"), None);
+    }
+
+    #[test]
+    fn extracts_request_bodies() {
+        let form = request("Content-Type: application/x-www-form-urlencoded", "a=1&b=x+y").form().unwrap();
+        assert_eq!(form.get("b"), Some("x y"));
+
+        let mut upload = request("Content-Type: multipart/form-data; boundary=\"B\"", BODY);
+        assert_eq!(parts(&mut upload.multipart().unwrap()).unwrap().len(), 2);
+
+        assert!(matches!(request("Content-Type: text/plain", "a=1").form(), Err(FormError::UnsupportedMediaType)));
+        assert!(matches!(request("Content-Type: text/plain", BODY).multipart(), Err(FormError::UnsupportedMediaType)));
+        assert!(matches!(request("Content-Type: multipart/form-data", BODY).multipart(), Err(FormError::Malformed)));
+    }
+}
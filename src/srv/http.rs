@@ -1,12 +1,20 @@
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Method {
     Unknown,
     Get,
+    Head,
     Post,
-    Put
+    Put,
+    Delete,
+    Connect,
+    Options,
+    Trace,
+    Patch,
+    /// Any other method token, kept as sent.
+    Extension(String)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Protocol {
     Unknown,
     V10,
@@ -15,7 +23,7 @@ pub enum Protocol {
     V30
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ContentType {
     Unknown,
     TextPlain,
@@ -29,6 +37,8 @@ pub enum ContentType {
     ApplicationJson,
     ApplicationWasm,
     ApplicationXml,
+    ApplicationFormUrlencoded,
+    MultipartFormData,
     AudioAac,
     AudioMpeg,
     AudioOgg,
@@ -38,7 +48,7 @@ pub enum ContentType {
     VideoWebm,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StatusCode {
     Unknown,
     Http100Continue, // info
@@ -118,47 +128,89 @@ pub enum StatusCode {
 }
 
 impl Method {
-    // pub const VALUES: [Self; 5] = [Self::Add, Self::ConfigFile, Self::Label, Self::Print, Self::Verbose];
-    pub fn as_str(&self) -> &'static str {
+    pub const VALUES: [Self; 9] = [
+        Self::Get, Self::Head, Self::Post, Self::Put, Self::Delete,
+        Self::Connect, Self::Options, Self::Trace, Self::Patch
+    ];
+
+    pub fn as_str(&self) -> &str {
         match self {
             Method::Unknown => "Unknown",
             Method::Get => "GET",
+            Method::Head => "HEAD",
             Method::Post => "POST",
-            Method::Put => "PUT"
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Connect => "CONNECT",
+            Method::Options => "OPTIONS",
+            Method::Trace => "TRACE",
+            Method::Patch => "PATCH",
+            Method::Extension(method) => method
         }
     }
 
+    /// Method names are case-sensitive, a value that is not a token is `Unknown`.
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(value: &str) -> Self {
         match value {
             "GET" => Method::Get,
+            "HEAD" => Method::Head,
             "POST" => Method::Post,
             "PUT" => Method::Put,
-            "Unknown" | _ => Method::Unknown
+            "DELETE" => Method::Delete,
+            "CONNECT" => Method::Connect,
+            "OPTIONS" => Method::Options,
+            "TRACE" => Method::Trace,
+            "PATCH" => Method::Patch,
+            _ if is_token(value) => Method::Extension(value.to_owned()),
+            _ => Method::Unknown
         }
     }
+
+    /// Whether the method is safe, a request with it only retrieves information.
+    pub fn is_safe(&self) -> bool {
+        matches!(self, Method::Get | Method::Head | Method::Options | Method::Trace)
+    }
 }
 
 impl Protocol {
     pub fn as_str(&self) -> &'static str {
         match self {
             Protocol::Unknown => "Unknown",
-            Protocol::V10 => "HTTP 1.0",
-            Protocol::V11 => "HTTP 1.1",
-            Protocol::V20 => "HTTP 2.0",
-            Protocol::V30 => "HTTP 3.0",
+            Protocol::V10 => "HTTP/1.0",
+            Protocol::V11 => "HTTP/1.1",
+            Protocol::V20 => "HTTP/2.0",
+            Protocol::V30 => "HTTP/3.0",
 
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(value: &str) -> Self {
-        match value {
-            "HTTP 1.0" => Protocol::V10,
-            "HTTP 1.1" => Protocol::V11,
-            "HTTP 2.0" => Protocol::V20,
-            "HTTP 3.0" => Protocol::V30,
-            "Unknown" | _ => Protocol::Unknown,
+        Protocol::parse(value).unwrap_or(Protocol::Unknown)
+    }
+
+    /// Parses an `HTTP/x.y` version, `None` if `value` is not one.
+    ///
+    /// Later minor versions of HTTP/1 are understood as HTTP/1.1, versions of an
+    /// unknown major are `Unknown`.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.strip_prefix("HTTP/")?.as_bytes() {
+            [major @ b'0'..=b'9', b'.', minor @ b'0'..=b'9'] => Some(match (major, minor) {
+                (b'1', b'0') => Protocol::V10,
+                (b'1', _) => Protocol::V11,
+                (b'2', _) => Protocol::V20,
+                (b'3', _) => Protocol::V30,
+                _ => Protocol::Unknown
+            }),
+            _ => None
         }
     }
+
+    /// Whether requests of this version can be answered on an HTTP/1 connection.
+    pub fn is_supported(&self) -> bool {
+        matches!(self, Protocol::V10 | Protocol::V11)
+    }
 }
 
 impl ContentType {
@@ -172,6 +224,8 @@ impl ContentType {
             ContentType::ApplicationJson => "application/json",
             ContentType::ApplicationWasm => "application/wasm",
             ContentType::ApplicationXml => "application/xml",
+            ContentType::ApplicationFormUrlencoded => "application/x-www-form-urlencoded",
+            ContentType::MultipartFormData => "multipart/form-data",
             ContentType::ImagePng => "image/png",
             ContentType::ImageJpeg => "image/jpeg",
             ContentType::ImageIcon => "image/vnd.microsoft.icon",
@@ -186,6 +240,7 @@ impl ContentType {
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(value: &str) -> Self {
         match value {
             "text/html" => ContentType::TextHtml,
@@ -195,6 +250,8 @@ impl ContentType {
             "application/json" => ContentType::ApplicationJson,
             "application/wasm" => ContentType::ApplicationWasm,
             "application/xml" => ContentType::ApplicationXml,
+            "application/x-www-form-urlencoded" => ContentType::ApplicationFormUrlencoded,
+            "multipart/form-data" => ContentType::MultipartFormData,
             "text/javascript" => ContentType::ApplicationJavascript,
             "text/xml" => ContentType::ApplicationXml,
             "image/jpeg" => ContentType::ImageJpeg,
@@ -207,10 +264,30 @@ impl ContentType {
             "video/mpeg" => ContentType::VideoMpeg,
             "video/mp4" => ContentType::VideoMp4,
             "video/webm" => ContentType::VideoWebm,
-            "*/*" | _ => ContentType::Unknown,
+            _ => ContentType::Unknown,
         }
     }
 
+    /// Parses a `Content-Type` field value, ignoring parameters such as `charset`.
+    pub fn from_header(value: &str) -> Self {
+        let media_type = value.split(';').next().unwrap_or_default().trim();
+        Self::from_str(&media_type.to_ascii_lowercase())
+    }
+
+    /// Whether bodies of this type shrink noticeably when compressed.
+    pub fn is_compressible(&self) -> bool {
+        matches!(
+            self,
+            ContentType::TextPlain
+            | ContentType::TextHtml
+            | ContentType::TextCss
+            | ContentType::ApplicationJavascript
+            | ContentType::ApplicationJson
+            | ContentType::ApplicationWasm
+            | ContentType::ApplicationXml
+        )
+    }
+
     pub fn guess(path: &std::path::Path) -> Self {
 
         match path.extension() {
@@ -236,10 +313,10 @@ impl ContentType {
                         "mpeg" => ContentType::VideoMpeg,
                         "mp4" => ContentType::VideoMp4,
                         "webm" => ContentType::AudioWebm,
-                        "*/*" | _ => ContentType::Unknown,
+                        _ => ContentType::Unknown,
                     }
                 } else {
-                    return ContentType::Unknown;
+                    ContentType::Unknown
                 }
             },
 
@@ -330,6 +407,7 @@ impl StatusCode {
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(value: &str) -> Self {
         match value {
             "100 Continue" => StatusCode::Http100Continue,
@@ -406,8 +484,180 @@ impl StatusCode {
             "524 A Timeout Occurred" => StatusCode::Http524ATimeoutOccurred,
             "525 SSL Handshake Failed" => StatusCode::Http525SslHandshakeFailed,
             "526 Invalid SSL Certificate" => StatusCode::Http526InvalidSslCertificate,
-            "Unknown" | _ => StatusCode::Unknown
+            _ => StatusCode::Unknown
         }
     }
 }
 
+
+/// Checks whether `value` is a non-empty `token` as defined by RFC 9110.
+pub fn is_token(value: &str) -> bool {
+    !value.is_empty() && value.bytes().all(|byte| {
+        byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+    })
+}
+
+/// Ordered collection of header fields.
+///
+/// Field names are matched case-insensitively and a name may occur more than once,
+/// entries keep the order in which they were added.
+#[derive(Debug, Clone, Default)]
+pub struct Headers {
+    entries: Vec<(String, String)>,
+}
+
+impl Headers {
+    pub const fn new() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    /// Returns the first value of the field `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns every value of the field `name` in order of appearance.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the comma-separated elements of every `name` field, trimmed and without empty ones.
+    pub fn get_list<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.get_all(name)
+            .flat_map(|value| value.split(','))
+            .map(|item| item.trim())
+            .filter(|item| !item.is_empty())
+    }
+
+    /// Checks whether the comma-separated field `name` contains `token`, ignoring case.
+    pub fn has_token(&self, name: &str, token: &str) -> bool {
+        self.get_list(name).any(|item| item.eq_ignore_ascii_case(token))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Adds a field, keeping the existing values of the same name.
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.entries.push((name.into(), value.into()));
+    }
+
+    /// Sets a field, replacing every existing value of the same name.
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        self.remove(&name);
+        self.entries.push((name, value.into()));
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.entries.retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Checks whether every field can be written as is: names are tokens and no value
+    /// contains CR, LF or NUL that would end the field early.
+    pub fn is_valid(&self) -> bool {
+        self.entries.iter().all(|(name, value)| {
+            is_token(name) && !value.bytes().any(|byte| matches!(byte, b'\r' | b'\n' | b'\0'))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(fields: &[(&str, &str)]) -> Headers {
+        let mut headers = Headers::new();
+
+        for (name, value) in fields {
+            headers.append(*name, *value);
+        }
+        headers
+    }
+
+    #[test]
+    fn parses_methods() {
+        for method in Method::VALUES {
+            assert_eq!(Method::from_str(method.as_str()), method);
+        }
+
+        assert_eq!(Method::from_str("PURGE"), Method::Extension("PURGE".to_owned()));
+        assert_eq!(Method::from_str("M-SEARCH").as_str(), "M-SEARCH");
+        // names are case-sensitive
+        assert_eq!(Method::from_str("get"), Method::Extension("get".to_owned()));
+        assert_eq!(Method::from_str(""), Method::Unknown);
+        assert_eq!(Method::from_str("GE T"), Method::Unknown);
+        assert_eq!(Method::from_str("GET/"), Method::Unknown);
+    }
+
+    #[test]
+    fn parses_versions() {
+        assert_eq!(Protocol::parse("HTTP/1.0"), Some(Protocol::V10));
+        assert_eq!(Protocol::parse("HTTP/1.1"), Some(Protocol::V11));
+        assert_eq!(Protocol::parse("HTTP/1.9"), Some(Protocol::V11));
+        assert_eq!(Protocol::parse("HTTP/2.0"), Some(Protocol::V20));
+        assert_eq!(Protocol::parse("HTTP/3.0"), Some(Protocol::V30));
+        assert_eq!(Protocol::parse("HTTP/0.9"), Some(Protocol::Unknown));
+        assert_eq!(Protocol::parse("HTTP/9.0"), Some(Protocol::Unknown));
+
+        // a single digit each and a case-sensitive name
+        for version in ["http/1.1", "Http/1.1", "HTTP/10.0", "HTTP/1.10", "HTTP/1", "HTTP/1.", "HTTP/.1", "HTTP/1,1", "HTTP/١.١", "HTTP/1.1 ", ""] {
+            assert_eq!(Protocol::parse(version), None, "{:?}", version);
+        }
+
+        assert!(Protocol::V10.is_supported() && Protocol::V11.is_supported());
+        assert!(!Protocol::V20.is_supported() && !Protocol::V30.is_supported() && !Protocol::Unknown.is_supported());
+    }
+
+    #[test]
+    fn matches_names_ignoring_case() {
+        let headers = headers(&[("Content-Type", "text/plain"), ("X-A", "1")]);
+
+        assert_eq!(headers.get("content-type"), Some("text/plain"));
+        assert_eq!(headers.get("CONTENT-TYPE"), Some("text/plain"));
+        assert!(headers.contains("x-a"));
+        assert_eq!(headers.get("X-B"), None);
+    }
+
+    #[test]
+    fn keeps_repeated_fields_in_order() {
+        let headers = headers(&[("Accept", "a, b"), ("X", "1"), ("accept", ""), ("ACCEPT", " c ,, d")]);
+
+        assert_eq!(headers.get("Accept"), Some("a, b"));
+        assert_eq!(headers.get_all("accept").collect::<Vec<_>>(), ["a, b", "", " c ,, d"]);
+        assert_eq!(headers.get_list("Accept").collect::<Vec<_>>(), ["a", "b", "c", "d"]);
+        assert!(headers.has_token("accept", "C"));
+        assert!(!headers.has_token("accept", "a, b"));
+    }
+
+    #[test]
+    fn inserts_and_removes_every_value() {
+        let mut headers = headers(&[("Vary", "a"), ("X", "1"), ("vary", "b")]);
+
+        headers.insert("VARY", "c");
+        assert_eq!(headers.get_all("Vary").collect::<Vec<_>>(), ["c"]);
+
+        headers.remove("vary");
+        assert!(!headers.contains("Vary"));
+        assert_eq!(headers.iter().collect::<Vec<_>>(), [("X", "1")]);
+    }
+}
@@ -0,0 +1,767 @@
+use std::fmt::{self, Write};
+use std::ops::Index;
+
+use super::http::{ContentType, StatusCode};
+use super::Request;
+
+/// Deepest nesting of arrays and objects accepted by the parser.
+pub const MAX_DEPTH: usize = 128;
+
+static NULL: Json = Json::Null;
+
+/// A JSON value.
+///
+/// Object members keep the order in which they were parsed or inserted, a name sent
+/// more than once resolves to its last value. Missing members and elements index as
+/// `Null`, and `Display` writes the value as compact JSON.
+///
+/// ```ignore
+/// let body = request.json()?;
+///
+/// let name = body["name"].as_str().unwrap_or("anonymous");
+/// let tags = body["tags"].as_array().map(Vec::len).unwrap_or(0);
+///
+/// Response::json(&Json::object([("greeting", format!("hello {}", name).into()), ("tags", tags.into())]))
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Json {
+    #[default]
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+
+    /// Parses a complete JSON text, whitespace around the value is allowed.
+    pub fn parse(text: &str) -> Result<Self, SyntaxError> {
+
+        let mut parser = Parser { bytes: text.as_bytes(), index: 0, depth: 0 };
+
+        parser.skip_whitespace();
+        let value = parser.value()?;
+        parser.skip_whitespace();
+
+        match parser.index < parser.bytes.len() {
+            true => Err(parser.error("unexpected data after the value")),
+            false => Ok(value)
+        }
+    }
+
+    /// Object with the given members, in order.
+    pub fn object<K: Into<String>>(members: impl IntoIterator<Item = (K, Json)>) -> Self {
+        Json::Object(members.into_iter().map(|(name, value)| (name.into(), value)).collect())
+    }
+
+    /// Value of the member `name`, `None` for a missing member or a value that is not an object.
+    pub fn get(&self, name: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().rev().find(|(key, _)| key == name).map(|(_, value)| value),
+            _ => None
+        }
+    }
+
+    /// Sets the member `name`, replacing a previous value. A non-object value becomes an empty object first.
+    pub fn insert(&mut self, name: &str, value: impl Into<Json>) {
+
+        if !matches!(self, Json::Object(_)) {
+            *self = Json::Object(vec![]);
+        }
+
+        if let Json::Object(members) = self {
+            members.retain(|(key, _)| key != name);
+            members.push((name.to_owned(), value.into()));
+        }
+    }
+
+    /// Appends an element. A non-array value becomes an empty array first.
+    pub fn push(&mut self, value: impl Into<Json>) {
+
+        if !matches!(self, Json::Array(_)) {
+            *self = Json::Array(vec![]);
+        }
+
+        if let Json::Array(elements) = self {
+            elements.push(value.into());
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Json::Null)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(value) => Some(*value),
+            _ => None
+        }
+    }
+
+    /// The number as an integer, `None` if it has a fraction or is out of range.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Number(value) if value.fract() == 0.0 && value.abs() < i64::MAX as f64 => Some(*value as i64),
+            _ => None
+        }
+    }
+
+    /// The number as an unsigned integer, `None` if it has a fraction, is negative or out of range.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(value) if value.fract() == 0.0 && *value >= 0.0 && *value < u64::MAX as f64 => Some(*value as u64),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match self {
+            Json::Array(elements) => Some(elements),
+            _ => None
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&Vec<(String, Json)>> {
+        match self {
+            Json::Object(members) => Some(members),
+            _ => None
+        }
+    }
+}
+
+impl Index<&str> for Json {
+    type Output = Json;
+
+    fn index(&self, name: &str) -> &Json {
+        self.get(name).unwrap_or(&NULL)
+    }
+}
+
+impl Index<usize> for Json {
+    type Output = Json;
+
+    fn index(&self, index: usize) -> &Json {
+        match self {
+            Json::Array(elements) => elements.get(index).unwrap_or(&NULL),
+            _ => &NULL
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            // JSON has no representation for NaN and infinities
+            Json::Number(value) if !value.is_finite() => f.write_str("null"),
+            Json::Number(value) => write!(f, "{}", value),
+            Json::String(value) => write_string(f, value),
+            Json::Array(elements) => {
+                f.write_char('[')?;
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(members) => {
+                f.write_char('{')?;
+                for (index, (name, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, name)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_owned())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(value: Vec<Json>) -> Self {
+        Json::Array(value)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(Json::Null)
+    }
+}
+
+macro_rules! from_number {
+    ($($number:ty),*) => {
+        $(
+            impl From<$number> for Json {
+                fn from(value: $number) -> Self {
+                    Json::Number(value as f64)
+                }
+            }
+        )*
+    };
+}
+
+from_number!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+
+/// Writes `text` as a quoted JSON string.
+fn write_string(f: &mut fmt::Formatter<'_>, text: &str) -> fmt::Result {
+
+    f.write_char('"')?;
+
+    for ch in text.chars() {
+        match ch {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\u{8}' => f.write_str("\\b")?,
+            '\u{c}' => f.write_str("\\f")?,
+            ch if (ch as u32) < 0x20 => write!(f, "\\u{:04x}", ch as u32)?,
+            ch => f.write_char(ch)?
+        }
+    }
+
+    f.write_char('"')
+}
+
+/// Position and cause of invalid JSON.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    /// Byte offset into the text.
+    pub offset: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    index: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+
+    fn error(&self, message: &'static str) -> SyntaxError {
+        SyntaxError { offset: self.index, message }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.index).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.index += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8, message: &'static str) -> Result<(), SyntaxError> {
+        match self.peek() == Some(byte) {
+            true => {
+                self.index += 1;
+                Ok(())
+            }
+            false => Err(self.error(message))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, SyntaxError> {
+        match self.peek() {
+            Some(b'{') => self.nested(Self::object),
+            Some(b'[') => self.nested(Self::array),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input"))
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Json, SyntaxError>) -> Result<Json, SyntaxError> {
+
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("nesting too deep"));
+        }
+
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+
+        value
+    }
+
+    fn literal(&mut self, literal: &str, value: Json) -> Result<Json, SyntaxError> {
+        match self.bytes[self.index..].starts_with(literal.as_bytes()) {
+            true => {
+                self.index += literal.len();
+                Ok(value)
+            }
+            false => Err(self.error("expected a value"))
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, SyntaxError> {
+
+        let mut elements = vec![];
+
+        self.index += 1;
+        self.skip_whitespace();
+
+        if self.peek() == Some(b']') {
+            self.index += 1;
+            return Ok(Json::Array(elements));
+        }
+
+        loop {
+            self.skip_whitespace();
+            elements.push(self.value()?);
+            self.skip_whitespace();
+
+            match self.peek() {
+                Some(b',') => self.index += 1,
+                Some(b']') => {
+                    self.index += 1;
+                    return Ok(Json::Array(elements));
+                }
+                _ => return Err(self.error("expected ',' or ']'"))
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, SyntaxError> {
+
+        let mut members = vec![];
+
+        self.index += 1;
+        self.skip_whitespace();
+
+        if self.peek() == Some(b'}') {
+            self.index += 1;
+            return Ok(Json::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a member name"));
+            }
+            let name = self.string()?;
+
+            self.skip_whitespace();
+            self.expect(b':', "expected ':'")?;
+            self.skip_whitespace();
+
+            members.push((name, self.value()?));
+            self.skip_whitespace();
+
+            match self.peek() {
+                Some(b',') => self.index += 1,
+                Some(b'}') => {
+                    self.index += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(self.error("expected ',' or '}'"))
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, SyntaxError> {
+
+        let start = self.index;
+
+        if self.peek() == Some(b'-') {
+            self.index += 1;
+        }
+
+        // no leading zeros, and the integer part is required
+        match self.peek() {
+            Some(b'0') => self.index += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            _ => return Err(self.error("expected a digit"))
+        }
+
+        if self.peek() == Some(b'.') {
+            self.index += 1;
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error("expected a digit"));
+            }
+            self.digits();
+        }
+
+        if let Some(b'e' | b'E') = self.peek() {
+            self.index += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.index += 1;
+            }
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error("expected a digit"));
+            }
+            self.digits();
+        }
+
+        // the grammar above only admits ASCII
+        let text = std::str::from_utf8(&self.bytes[start..self.index]).unwrap_or_default();
+
+        match text.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(Json::Number(value)),
+            _ => Err(SyntaxError { offset: start, message: "number out of range" })
+        }
+    }
+
+    fn digits(&mut self) {
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.index += 1;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, SyntaxError> {
+
+        let mut value = String::new();
+
+        self.index += 1;
+
+        loop {
+            // copy the run up to the next quote, escape or control character at once
+            let start = self.index;
+            while let Some(byte) = self.peek() {
+                if byte == b'"' || byte == b'\\' || byte < 0x20 {
+                    break;
+                }
+                self.index += 1;
+            }
+            // the text came from a &str and the run ends on an ASCII byte
+            value.push_str(std::str::from_utf8(&self.bytes[start..self.index]).unwrap_or_default());
+
+            match self.peek() {
+                Some(b'"') => {
+                    self.index += 1;
+                    return Ok(value);
+                }
+                Some(b'\\') => {
+                    self.index += 1;
+                    value.push(self.escape()?);
+                }
+                Some(_) => return Err(self.error("control character in string")),
+                None => return Err(self.error("unterminated string"))
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, SyntaxError> {
+
+        let escaped = match self.peek() {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                self.index += 1;
+                return self.unicode();
+            }
+            _ => return Err(self.error("invalid escape"))
+        };
+
+        self.index += 1;
+        Ok(escaped)
+    }
+
+    /// Decodes the digits of a `\u` escape, joining a surrogate pair.
+    fn unicode(&mut self) -> Result<char, SyntaxError> {
+
+        let start = self.index;
+        let high = self.hex()?;
+
+        let code = match high {
+            0xD800..=0xDBFF => {
+                if !self.bytes[self.index..].starts_with(b"\\u") {
+                    return Err(SyntaxError { offset: start, message: "unpaired surrogate" });
+                }
+                self.index += 2;
+
+                match self.hex()? {
+                    low @ 0xDC00..=0xDFFF => 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00),
+                    _ => return Err(SyntaxError { offset: start, message: "unpaired surrogate" })
+                }
+            }
+            0xDC00..=0xDFFF => return Err(SyntaxError { offset: start, message: "unpaired surrogate" }),
+            code => code
+        };
+
+        char::from_u32(code).ok_or(SyntaxError { offset: start, message: "invalid escape" })
+    }
+
+    fn hex(&mut self) -> Result<u32, SyntaxError> {
+
+        let digits = self.bytes
+            .get(self.index..self.index + 4)
+            .filter(|digits| digits.iter().all(u8::is_ascii_hexdigit))
+            .ok_or(self.error("invalid escape"))?;
+
+        self.index += 4;
+
+        // four ASCII hexadecimal digits always parse
+        Ok(u32::from_str_radix(std::str::from_utf8(digits).unwrap_or_default(), 16).unwrap_or_default())
+    }
+}
+
+#[derive(Debug)]
+pub enum JsonError {
+    /// The body is not `application/json`.
+    UnsupportedMediaType,
+    /// The body is not valid UTF-8.
+    Encoding,
+    Syntax(SyntaxError),
+}
+
+impl JsonError {
+    /// Status code to answer the client with.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            JsonError::UnsupportedMediaType => StatusCode::Http415UnsupportedMediaType,
+            JsonError::Encoding | JsonError::Syntax(_) => StatusCode::Http400BadRequest,
+        }
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::UnsupportedMediaType => write!(f, "body is not application/json"),
+            JsonError::Encoding => write!(f, "body is not valid UTF-8"),
+            JsonError::Syntax(err) => write!(f, "invalid JSON: {}", err)
+        }
+    }
+}
+
+impl std::error::Error for JsonError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            JsonError::Syntax(err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<SyntaxError> for JsonError {
+    fn from(err: SyntaxError) -> Self {
+        JsonError::Syntax(err)
+    }
+}
+
+impl Request {
+
+    /// Parses an `application/json` body.
+    pub fn json(&self) -> Result<Json, JsonError> {
+
+        if self.content_type != ContentType::ApplicationJson {
+            return Err(JsonError::UnsupportedMediaType);
+        }
+
+        let text = std::str::from_utf8(&self.content).map_err(|_| JsonError::Encoding)?;
+
+        // a byte order mark may be ignored
+        Ok(Json::parse(text.strip_prefix('\u{feff}').unwrap_or(text))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::srv::reader::Limits;
+
+    fn error(text: &str) -> SyntaxError {
+        Json::parse(text).unwrap_err()
+    }
+
+    #[test]
+    fn parses_every_kind_of_value() {
+        let value = Json::parse(" {\"a\": [1, -2.5e2, 0.125, true, false, null], \"b\": {\"c\": \"d\"}, \"e\": []}\r\n\t").unwrap();
+
+        assert_eq!(value, Json::object([
+            ("a", Json::Array(vec![1.into(), (-250.0).into(), 0.125.into(), true.into(), false.into(), Json::Null])),
+            ("b", Json::object([("c", "d".into())])),
+            ("e", Json::Array(vec![])),
+        ]));
+        assert_eq!(Json::parse("0").unwrap(), Json::Number(0.0));
+        assert_eq!(Json::parse("-0.0E+1").unwrap(), Json::Number(-0.0));
+    }
+
+    #[test]
+    fn decodes_escapes_and_surrogate_pairs() {
+        let value = Json::parse(r#""\"\\\/\b\f\n\r\t\u0041\u00e9\u20AC\ud83d\ude00 \u00E9\uD83D\uDE00""#).unwrap();
+
+        assert_eq!(value.as_str(), Some("\"\\/\u{8}\u{c}\n\r\tA\u{e9}\u{20ac}\u{1f600} \u{e9}\u{1f600}"));
+    }
+
+    #[test]
+    fn refuses_invalid_strings() {
+        assert_eq!(error(r#""\ud83d""#).message, "unpaired surrogate");
+        assert_eq!(error(r#""\ud83dA""#).message, "unpaired surrogate");
+        assert_eq!(error(r#""\ude00\ud83d""#).message, "unpaired surrogate");
+        assert_eq!(error(r#""\u12""#).message, "invalid escape");
+        assert_eq!(error(r#""\x""#).message, "invalid escape");
+        assert_eq!(error("\"a\tb\"").message, "control character in string");
+        assert_eq!(error("\"abc").message, "unterminated string");
+        assert_eq!(error("'abc'").message, "expected a value");
+    }
+
+    #[test]
+    fn refuses_invalid_numbers() {
+        assert_eq!(error("01"), SyntaxError { offset: 1, message: "unexpected data after the value" });
+        assert_eq!(error("-01").offset, 2);
+        for text in ["1.", ".5", "-", "+1", "1e", "1e+", "0x10", "NaN", "Infinity", "1.e5"] {
+            assert!(Json::parse(text).is_err(), "{}", text);
+        }
+        assert_eq!(error("1e999").message, "number out of range");
+    }
+
+    #[test]
+    fn refuses_invalid_structure() {
+        assert_eq!(error("").message, "unexpected end of input");
+        assert_eq!(error("[1,]").message, "expected a value");
+        assert_eq!(error("[1 2]").message, "expected ',' or ']'");
+        assert_eq!(error("{\"a\":1,}").message, "expected a member name");
+        assert_eq!(error("{a:1}").message, "expected a member name");
+        assert_eq!(error("{\"a\" 1}").message, "expected ':'");
+        assert_eq!(error("{\"a\":1").message, "expected ',' or '}'");
+        assert_eq!(error("tru").message, "expected a value");
+        assert_eq!(error("\u{a0}1").message, "expected a value");
+    }
+
+    #[test]
+    fn refuses_trailing_data() {
+        assert_eq!(error("1 2"), SyntaxError { offset: 2, message: "unexpected data after the value" });
+        assert_eq!(error("{} {}").offset, 3);
+        assert_eq!(error("null,").offset, 4);
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth: usize| "[".repeat(depth) + &"]".repeat(depth);
+
+        assert!(Json::parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(error(&nested(MAX_DEPTH + 1)), SyntaxError { offset: MAX_DEPTH, message: "nesting too deep" });
+        assert_eq!(error(&"{\"a\":".repeat(100_000)).message, "nesting too deep");
+    }
+
+    #[test]
+    fn serializes_compactly_and_escapes() {
+        let value = Json::object([
+            ("text", "quote \" backslash \\ slash / \n\r\t\u{8}\u{c}\u{1}\u{1f} é😀".into()),
+            ("numbers", Json::Array(vec![1.into(), (-0.5).into(), 1e21.into(), f64::NAN.into(), f64::INFINITY.into()])),
+            ("none", Option::<u8>::None.into()),
+            ("\"key\"", true.into()),
+        ]);
+
+        assert_eq!(
+            value.to_string(),
+            r#"{"text":"quote \" backslash \\ slash / \n\r\t\b\f\u0001\u001f é😀","numbers":[1,-0.5,1000000000000000000000,null,null],"none":null,"\"key\"":true}"#
+        );
+    }
+
+    #[test]
+    fn round_trips_through_display() {
+        for text in [
+            r#"{"a":[1,2.5,-0.001,1e-7,123456789012345,true,false,null],"b":{"c":"\u0000\"\\\u00e9\ud83d\ude00"},"d":[]}"#,
+            r#"[{},[[]],"",0]"#,
+            r#""\uD83D\uDE00 ""#,
+        ] {
+            let value = Json::parse(text).unwrap();
+            assert_eq!(Json::parse(&value.to_string()).unwrap(), value, "{}", text);
+        }
+    }
+
+    #[test]
+    fn accesses_members_and_elements() {
+        let mut value = Json::parse(r#"{"a":1,"list":[10,"x"],"a":2}"#).unwrap();
+
+        assert_eq!(value["a"].as_i64(), Some(2));
+        assert_eq!(value["list"][1].as_str(), Some("x"));
+        assert_eq!(value["list"][0].as_u64(), Some(10));
+        assert!(value["missing"][3]["deep"].is_null());
+        assert_eq!(Json::Number(1.5).as_i64(), None);
+        assert_eq!(Json::Number(-1.0).as_u64(), None);
+
+        value.insert("a", "replaced");
+        value["list"].as_array().unwrap();
+        assert_eq!(value.to_string(), r#"{"list":[10,"x"],"a":"replaced"}"#);
+
+        let mut list = Json::Null;
+        list.push(1);
+        list.push("two");
+        assert_eq!(list.to_string(), r#"[1,"two"]"#);
+    }
+
+    #[test]
+    fn extracts_request_bodies() {
+        let request = |content_type: &str, body: &[u8]| {
+            let mut message = format!("POST / HTTP/1.1\r\nHost: x\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n", content_type, body.len()).into_bytes();
+            message.extend_from_slice(body);
+            Request::from(&mut message.as_slice(), &Limits::default()).unwrap()
+        };
+
+        assert_eq!(request("application/json; charset=utf-8", b"{\"a\":1}").json().unwrap()["a"].as_i64(), Some(1));
+        assert_eq!(request("application/json", "\u{feff}[]".as_bytes()).json().unwrap(), Json::Array(vec![]));
+
+        let unsupported = request("text/plain", b"{}").json().unwrap_err();
+        assert_eq!(unsupported.status(), StatusCode::Http415UnsupportedMediaType);
+
+        for body in [&b"{"[..], b"", b"\xff"] {
+            assert_eq!(request("application/json", body).json().unwrap_err().status(), StatusCode::Http400BadRequest);
+        }
+    }
+}
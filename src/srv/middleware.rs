@@ -0,0 +1,159 @@
+use std::sync::Arc;
+
+use super::{Request, Response};
+
+/// Code wrapped around request handling.
+///
+/// A middleware receives the request and the rest of the chain as [`Next`]. It may
+/// answer on its own without calling `next`, change the request before passing it on,
+/// or change the response `next` returns.
+///
+/// ```ignore
+/// let server = Server::new()
+///     .middleware(|request: Request, next: Next| {
+///         let mut response = next.run(request);
+///         response.headers.insert("X-Frame-Options", "DENY");
+///         response
+///     });
+/// ```
+pub trait Middleware: Send + Sync {
+    fn handle(&self, request: Request, next: Next) -> Response;
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(Request, Next) -> Response + Send + Sync,
+{
+    fn handle(&self, request: Request, next: Next) -> Response {
+        self(request, next)
+    }
+}
+
+/// The remaining middleware and the handler at the end of the chain.
+pub struct Next<'a> {
+    chain: &'a [Arc<dyn Middleware>],
+    endpoint: &'a dyn Fn(Request) -> Response,
+}
+
+impl<'a> Next<'a> {
+
+    pub fn new(chain: &'a [Arc<dyn Middleware>], endpoint: &'a dyn Fn(Request) -> Response) -> Self {
+        Self { chain, endpoint }
+    }
+
+    /// Passes `request` to the next middleware, or to the handler once the chain is done.
+    pub fn run(self, request: Request) -> Response {
+        match self.chain.split_first() {
+            Some((middleware, chain)) => middleware.handle(request, Next::new(chain, self.endpoint)),
+            None => (self.endpoint)(request)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use super::super::http::StatusCode;
+    use super::super::reader::Limits;
+    use super::super::{Router, Server};
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    /// Middleware noting when the request passes in and the response passes out.
+    fn logged(log: &Log, name: &'static str) -> impl Fn(Request, Next) -> Response + Send + Sync {
+        let log = Arc::clone(log);
+        move |request: Request, next: Next| {
+            log.lock().unwrap().push(format!("{} in", name));
+            let response = next.run(request);
+            log.lock().unwrap().push(format!("{} out {}", name, response.status.as_str()));
+            response
+        }
+    }
+
+    fn get(server: &Server, path: &str) -> Response {
+        let head = format!("GET {} HTTP/1.1\r\n\r\n", path);
+        server.respond(Request::from(&mut head.as_bytes(), &Limits::default()).unwrap())
+    }
+
+    #[test]
+    fn runs_in_registration_order_from_the_outside_in() {
+        let log = Log::default();
+        let handler_log = Arc::clone(&log);
+
+        let group = Router::new()
+            .get("/stats", move |_: Request| {
+                handler_log.lock().unwrap().push("handler".to_owned());
+                Response::text("stats")
+            })
+            .middleware(logged(&log, "group"));
+
+        let routes = Router::new()
+            .middleware(logged(&log, "router 1"))
+            .nest("/admin", group)
+            .middleware(logged(&log, "router 2"));
+
+        let server = Server::new()
+            .middleware(logged(&log, "server 1"))
+            .middleware(logged(&log, "server 2"))
+            .routes(routes);
+
+        get(&server, "/admin/stats");
+
+        assert_eq!(*log.lock().unwrap(), [
+            "server 1 in", "server 2 in", "router 1 in", "router 2 in", "group in",
+            "handler",
+            "group out 200 OK", "router 2 out 200 OK", "router 1 out 200 OK", "server 2 out 200 OK", "server 1 out 200 OK"
+        ]);
+
+        // only the server's middleware runs when no route matches
+        log.lock().unwrap().clear();
+        get(&server, "/missing");
+
+        assert_eq!(*log.lock().unwrap(), [
+            "server 1 in", "server 2 in", "server 2 out 404 Not Found", "server 1 out 404 Not Found"
+        ]);
+    }
+
+    #[test]
+    fn answers_without_calling_next() {
+        let log = Log::default();
+        let handler_log = Arc::clone(&log);
+
+        let routes = Router::new()
+            .get("/private", move |_: Request| {
+                handler_log.lock().unwrap().push("handler".to_owned());
+                Response::text("secret")
+            })
+            .middleware(|request: Request, next: Next| match request.header("Authorization") {
+                Some(_) => next.run(request),
+                None => Response::error(StatusCode::Http401Unauthorized)
+            })
+            .middleware(logged(&log, "inner"));
+
+        let server = Server::new().middleware(logged(&log, "outer")).routes(routes);
+
+        assert_eq!(get(&server, "/private").status, StatusCode::Http401Unauthorized);
+        assert_eq!(*log.lock().unwrap(), ["outer in", "outer out 401 Unauthorized"]);
+    }
+
+    #[test]
+    fn passes_changes_along_the_chain() {
+        let routes = Router::new().get("/", |request: Request| {
+            Response::text(request.header("X-User").unwrap_or("nobody").to_owned())
+        });
+
+        let server = Server::new()
+            .middleware(|mut request: Request, next: Next| {
+                request.headers.insert("X-User", "ann");
+                next.run(request)
+            })
+            .middleware(|request: Request, next: Next| next.run(request).with_header("X-Frame-Options", "DENY"))
+            .routes(routes);
+
+        let response = get(&server, "/");
+        assert_eq!(response.body.as_bytes(), b"ann");
+        assert_eq!(response.headers.get("X-Frame-Options"), Some("DENY"));
+    }
+}
@@ -1,21 +1,59 @@
 use std::io::prelude::*;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind};
 use std::net::{TcpListener, TcpStream};
-use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crate::pool::ThreadPool;
+use crate::error::{self, Error};
+use crate::pool::{JobQueue, QueuePolicy, ThreadPool};
 
 pub mod http;
 use http::*;
 
+pub mod router;
+use router::*;
+
+pub mod reader;
+use reader::*;
+
+pub mod chunked;
+use chunked::*;
+
+pub mod files;
+use files::*;
+
+pub mod middleware;
+use middleware::*;
+
+pub mod shutdown;
+use shutdown::*;
+
+pub mod query;
+use query::*;
+
+pub mod date;
+pub mod range;
+pub mod conditional;
+pub mod compress;
+pub mod autoindex;
+pub mod form;
+pub mod json;
+
 #[derive(Debug)]
 pub struct Request {
     pub path: String,
-    pub params: Vec<(String, String)>,
+    pub query: Query,
+    pub path_params: Params,
     pub method: Method,
     pub protocol: Protocol,
+    pub headers: Headers,
+    pub trailers: Headers,
     pub content: Vec<u8>,
+    /// Body still on the connection, set instead of `content` for uploads.
+    pub body: Option<Body>,
     pub content_type: ContentType,
     pub content_size: usize,
     pub status: StatusCode
@@ -24,23 +62,65 @@ pub enum Content {
     HeapString(String),
     StaticString(&'static str),
     Raw(Vec<u8>),
+    /// Body read while it is sent, with its length if known in advance.
+    ///
+    /// A body of unknown length is sent chunked to HTTP/1.1 clients and
+    /// delimited by closing the connection otherwise.
+    Stream(Box<dyn Read + Send>, Option<u64>),
+    /// File sent from its current position, with the number of bytes to send.
+    File(File, u64),
     None
 }
 pub struct Response {
     pub body: Content,
     pub content_type: ContentType,
     pub status: StatusCode,
+    pub headers: Headers,
 }
 
-type Route = dyn Fn(Request) -> Response + Send + Sync;
-type RouteMap = Arc<HashMap<&'static str, Arc<Route>>>;
+/// Largest piece of a streamed body held in memory while it is written.
+pub const WRITE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Seconds a client turned away with 503 is asked to wait before retrying.
+pub const OVERLOAD_RETRY_AFTER: u64 = 1;
+
+/// How long an idle persistent connection is kept open by default.
+pub const DEFAULT_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a client may take to send a request line and header fields by default.
+pub const DEFAULT_HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a client may take to send a request body by default.
+pub const DEFAULT_BODY_TIMEOUT: Duration = Duration::from_secs(60);
 
-#[derive(Default)]
+/// How long a streamed upload may go without receiving data by default.
+pub const DEFAULT_UPLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a single write to a client may block by default.
+pub const DEFAULT_WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Default, Clone)]
 pub struct Server {
     pub address: String,
     pub public: Arc<Option<PathBuf>>,
     pub max_connections: usize,
-    pub routes: RouteMap
+    pub routes: Arc<Router>,
+    pub keep_alive_timeout: Duration,
+    pub header_timeout: Duration,
+    pub body_timeout: Duration,
+    pub upload_timeout: Duration,
+    pub write_timeout: Duration,
+    pub limits: Limits,
+    pub symlinks: SymlinkPolicy,
+    pub weak_etags: bool,
+    pub compression: bool,
+    pub precompressed: bool,
+    pub autoindex: bool,
+    pub spa: Option<String>,
+    pub middleware: Vec<Arc<dyn Middleware>>,
+    pub shutdown: ShutdownHandle,
+    pub shutdown_timeout: Duration,
+    pub queue: Arc<JobQueue>
 }
 
 pub const RES_NOT_FOUND: Response = Response {
@@ -51,7 +131,8 @@ pub const RES_NOT_FOUND: Response = Response {
 <body><h1>Not Found</h1>The requested URL was not found on this server.</body>
 </html>"),
     status: StatusCode::Http404NotFound,
-    content_type: ContentType::TextHtml
+    content_type: ContentType::TextHtml,
+    headers: Headers::new()
 };
 
 pub const RES_SERVER_ERROR: Response = Response {
@@ -62,13 +143,20 @@ pub const RES_SERVER_ERROR: Response = Response {
 <body><h1>Iternal Server Error</h1>Yet another error to catch.</body>
 </html>"),
     status: StatusCode::Http500InternalServerError,
-    content_type: ContentType::TextHtml
+    content_type: ContentType::TextHtml,
+    headers: Headers::new()
 };
 
 impl Server {
 
     pub fn new() -> Self {
         Self {
+            keep_alive_timeout: DEFAULT_KEEP_ALIVE_TIMEOUT,
+            header_timeout: DEFAULT_HEADER_TIMEOUT,
+            body_timeout: DEFAULT_BODY_TIMEOUT,
+            upload_timeout: DEFAULT_UPLOAD_TIMEOUT,
+            write_timeout: DEFAULT_WRITE_TIMEOUT,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
             ..Default::default()
         }
     }
@@ -88,187 +176,1108 @@ impl Server {
         self
     }
 
-    pub fn routes(mut self, routes: RouteMap) -> Self {
-        self.routes = routes;
+    pub fn routes(mut self, routes: Router) -> Self {
+        self.routes = Arc::new(routes);
+        self
+    }
+
+    /// Sets how symbolic links inside the public directory are followed.
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Compresses text responses with gzip or deflate when the client accepts it.
+    pub fn compression(mut self, enabled: bool) -> Self {
+        self.compression = enabled;
+        self
+    }
+
+    /// Answers page navigations to missing paths with `document`, a path inside the public
+    /// directory, for single-page applications with client-side routing.
+    pub fn spa(mut self, document: &str) -> Self {
+        self.spa = Some(document.to_owned());
+        self
+    }
+
+    /// Runs `middleware` around every request, for routes and static files alike.
+    ///
+    /// Middleware runs in registration order, before any middleware of the matched route.
+    pub fn middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Lists the content of public directories without an `index.html`.
+    pub fn autoindex(mut self, enabled: bool) -> Self {
+        self.autoindex = enabled;
+        self
+    }
+
+    /// Serves `.br` and `.gz` files found next to a requested static file to clients accepting them.
+    pub fn precompressed(mut self, enabled: bool) -> Self {
+        self.precompressed = enabled;
+        self
+    }
+
+    /// Sends weak instead of strong entity tags for static files.
+    pub fn weak_etags(mut self, weak: bool) -> Self {
+        self.weak_etags = weak;
+        self
+    }
+
+    /// Sets the largest accepted request line plus header block, larger ones are answered with 431.
+    pub fn max_header_size(mut self, bytes: usize) -> Self {
+        self.limits.max_header_size = bytes;
+        self
+    }
+
+    /// Sets the largest accepted request body, larger ones are answered with 413.
+    pub fn max_body_size(mut self, bytes: usize) -> Self {
+        self.limits.max_body_size = bytes;
+        self
+    }
+
+    /// Sets the largest accepted `multipart/form-data` upload, larger ones are answered
+    /// with 413. Uploads with a known length are streamed to the handler instead of being
+    /// held in memory, so this limit may well exceed [`Server::max_body_size`].
+    pub fn max_upload_size(mut self, bytes: usize) -> Self {
+        self.limits.max_upload_size = bytes;
         self
     }
-    
-    pub fn run(&self) -> () {
+
+    /// Sets how long an idle connection waits for the next request.
+    /// A zero duration disables persistent connections.
+    pub fn keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.keep_alive_timeout = timeout;
+        self
+    }
+
+    /// Sets how long a client may take to send the request line and header fields,
+    /// slower ones are answered with 408. A new connection has to send its first
+    /// request within this time. A zero duration disables the limit.
+    pub fn header_timeout(mut self, timeout: Duration) -> Self {
+        self.header_timeout = timeout;
+        self
+    }
+
+    /// Sets how long a client may take to send a request body, slower ones are answered
+    /// with 408. A zero duration disables the limit.
+    pub fn body_timeout(mut self, timeout: Duration) -> Self {
+        self.body_timeout = timeout;
+        self
+    }
+
+    /// Sets how long a streamed `multipart/form-data` upload may go without receiving
+    /// data, stalled ones are answered with 408. Unlike [`Server::body_timeout`] this
+    /// does not limit the upload as a whole. A zero duration disables the limit.
+    pub fn upload_timeout(mut self, timeout: Duration) -> Self {
+        self.upload_timeout = timeout;
+        self
+    }
+
+    /// Sets how long a single write to a client may block before the connection is
+    /// dropped. A zero duration disables the limit.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    /// Sets how long in-flight requests may take to finish after shutdown is requested.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Limits the connections waiting for a free worker to `capacity`, applying `policy`
+    /// when the queue is full. Refused connections are answered with 503.
+    pub fn queue(mut self, capacity: usize, policy: QueuePolicy) -> Self {
+        self.queue = Arc::new(JobQueue::bounded(capacity, policy));
+        self
+    }
+
+    /// Queue of accepted connections waiting for a worker, for monitoring its depth.
+    pub fn job_queue(&self) -> Arc<JobQueue> {
+        Arc::clone(&self.queue)
+    }
+
+    /// Handle that stops [`Server::run`] from another thread.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Accepts connections until shutdown is requested through [`Server::shutdown_handle`].
+    ///
+    /// Fails only if the address can not be listened on. Errors on single connections are
+    /// logged and do not stop the server.
+    pub fn run(&self) -> error::Result<()> {
 
         let listener = TcpListener::bind(&self.address)
-            .expect("Error: cannor bind address");
-        let pool = ThreadPool::new(self.max_connections);
+            .map_err(|err| Error::Bind(self.address.clone(), err))?;
+        let pool = ThreadPool::with_queue(self.max_connections, Arc::clone(&self.queue));
+        let server = Arc::new(self.clone());
+
+        if !self.shutdown.listening(listener.local_addr()?) {
+            return Ok(());
+        }
 
         for stream_res in listener.incoming() {
 
-            let routes = Arc::clone(&self.routes);
-            let public = Arc::clone(&self.public);
-
-            pool.execute(move || {
-
-                let mut stream = stream_res.unwrap();
-
-                let request = Request::from(&mut stream); // TODO fix empty param bug
-
-                let response: Response = match routes.get(request.path.as_str()) {
-                    Some(r) => r(request),
-                    None => match public.as_ref().as_ref() {
-                        Some(val) => {
-
-                            let resource_path = if request.path != "/" {
-                                Path::new(val).join(&request.path.as_str()[1..])
-                            } else {
-                                Path::new(val).join("index.html")
-                            };
-
-                            let content_type_guessed = ContentType::guess(&resource_path);
-
-                            if resource_path.is_file() {
-
-                                match content_type_guessed {
-                                    ContentType::TextHtml
-                                    | ContentType::TextCss
-                                    | ContentType::ApplicationJavascript
-                                    | ContentType::ApplicationJson => {
-                                        match std::fs::read_to_string(&resource_path) {
-                                            Ok(res) => Response {
-                                                body: Content::HeapString(res),
-                                                status: StatusCode::Http200Ok,
-                                                content_type: content_type_guessed
-                                            },
-                                            Err(_) => RES_SERVER_ERROR
-                                        }
-                                    }
-                                    _ => {
-                                        match std::fs::read(&resource_path) {
-                                            Ok(res) => Response {
-                                                body: Content::Raw(res),
-                                                status: StatusCode::Http200Ok,
-                                                content_type: content_type_guessed
-                                            },
-                                            Err(_) => RES_SERVER_ERROR
-                                        }
-                                    }
-                                }
-
-                            } else {
-                                RES_NOT_FOUND
-                            }
-                        }
-                        None => RES_NOT_FOUND
-                    }
-                };
-
-                match &response.body {
-                    Content::HeapString(string) => {
-                        stream.write(format!(
-                            "HTTP/1.1 {}\r\nContent-Length: {}\r\nContent-Type: {}\r\n\r\n{}",
-                            response.status.as_str(),
-                            string.len(),
-                            response.content_type.as_str(),
-                            string.as_str()
-                        ).as_bytes()).unwrap();
-                    },
-                    Content::StaticString(string) => {
-                        stream.write(format!(
-                            "HTTP/1.1 {}\r\nContent-Length: {}\r\nContent-Type: {}\r\n\r\n{}",
-                            response.status.as_str(),
-                            string.len(),
-                            response.content_type.as_str(),
-                            *string
-                        ).as_bytes()).unwrap();
-                    },
-                    Content::Raw(data) => {
-                        stream.write(format!(
-                            "HTTP/1.1 {}\r\nContent-Length: {}\r\nContent-Type: {}\r\n\r\n",
-                            response.status.as_str(),
-                            data.len(),
-                            response.content_type.as_str(),
-                        ).as_bytes()).unwrap();
-                        stream.write(data).unwrap();
+            if self.shutdown.is_shutdown() {
+                break;
+            }
+
+            let stream = match stream_res {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("Error accepting connection: {}", err);
+                    continue;
+                }
+            };
+            let connection = match self.shutdown.track(&stream) {
+                Ok(connection) => connection,
+                Err(_) => continue
+            };
+
+            let server = Arc::clone(&server);
+            let overflow = stream.try_clone();
+
+            pool.execute_or_else(move || {
+                let peer = stream.peer_addr();
+
+                if let Err(err) = server.serve_connection(stream, connection) {
+                    match peer {
+                        Ok(peer) => eprintln!("Connection from {}: {}", peer, err),
+                        Err(_) => eprintln!("Connection: {}", err)
                     }
-                    _ => ()
-                };
+                }
+            }, move || {
+                if let Ok(mut stream) = overflow {
+                    let mut response = Response::error(StatusCode::Http503ServiceUnavailable)
+                        .with_header("Retry-After", &OVERLOAD_RETRY_AFTER.to_string())
+                        .with_header("Connection", "close");
+                    let _ = response.write_to(Protocol::V11, &mut stream).and_then(|_| stream.flush());
+                }
+            });
+        }
+
+        drop(listener);
+        self.shutdown.drain(self.shutdown_timeout);
+
+        Ok(())
+    }
+
+    /// Answers requests on `stream` until the client or the server asks to close it.
+    ///
+    /// Pipelined requests are left in the read buffer and answered in order.
+    fn serve_connection(&self, stream: TcpStream, connection: Tracked) -> error::Result<()> {
+
+        let mut writer = stream.try_clone()?;
+
+        writer.set_write_timeout(match self.write_timeout.is_zero() {
+            true => None,
+            false => Some(self.write_timeout)
+        })?;
 
-                stream.flush().unwrap();
+        // shared with the body of a streamed upload
+        let inbound = Arc::new(Mutex::new(Inbound {
+            reader: BufReader::new(DeadlineStream::new(stream)),
+            unread: 0
+        }));
+
+        // a new connection has to send its request like any header block,
+        // between requests a persistent one may idle up to the keep-alive timeout
+        inbound.lock().unwrap().reader.get_mut().set_timeout(self.header_timeout);
+
+        for index in 0.. {
+
+            let mut guard = inbound.lock().unwrap();
+            let Inbound { reader, unread } = &mut *guard;
+
+            if index > 0 {
+                reader.get_mut().set_timeout(self.keep_alive_timeout);
+            }
+
+            match reader.fill_buf() {
+                Ok(buf) if !buf.is_empty() => (),
+                Ok(_) => break,
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => break,
+                Err(err) => return Err(err.into())
+            }
+
+            if index > 0 {
+                reader.get_mut().set_timeout(self.header_timeout);
+            }
+            connection.busy();
+
+            let request = Request::from_head(reader, &self.limits).and_then(|mut request| {
+                reader.get_mut().set_timeout(self.body_timeout);
+
+                // chunked uploads are rare enough to be buffered like any other body
+                let upload = request.content_type == ContentType::MultipartFormData
+                    && !request.headers.contains("Transfer-Encoding");
+
+                match upload {
+                    true if request.content_size > self.limits.max_upload_size => Err(ReadError::BodyTooLarge),
+                    true => {
+                        // a large upload may take longer than any fixed deadline
+                        reader.get_mut().set_idle_timeout(self.upload_timeout);
+                        *unread = request.content_size as u64;
+                        request.body = Some(Body::new(Arc::clone(&inbound)));
+                        Ok(request)
+                    }
+                    false => request.read_content(reader, &self.limits).map(|_| request)
+                }
             });
+
+            drop(guard);
+
+            let request = match request {
+                Ok(request) => request,
+                Err(err @ (ReadError::Closed | ReadError::Io(_))) => return Err(err.into()),
+                // the rest of the stream can not be trusted to start at a request boundary
+                Err(err) => {
+                    Response::error(err.status())
+                        .with_header("Connection", "close")
+                        .write_to(Protocol::V11, &mut writer)?;
+                    writer.flush()?;
+                    return Err(err.into());
+                }
+            };
+
+            let mut keep_alive = request.keep_alive() && !self.keep_alive_timeout.is_zero();
+            let protocol = request.protocol;
+            let head_only = request.method == Method::Head;
+            let accept_encoding = request.header("Accept-Encoding").map(str::to_owned);
+
+            let mut response = match panic::catch_unwind(AssertUnwindSafe(|| self.respond(request))) {
+                Ok(response) => response,
+                // the panic itself is already reported by the panic hook
+                Err(_) => RES_SERVER_ERROR
+            };
+
+            // the next request starts after the part of an upload the handler left unread
+            if inbound.lock().unwrap().unread > 0 {
+                keep_alive = false;
+            }
+
+            if self.compression {
+                compress::encode_response(&mut response, accept_encoding.as_deref());
+            }
+
+            if response.headers.has_token("Connection", "close") || self.shutdown.is_shutdown() {
+                keep_alive = false;
+            }
+
+            if response.body.len().is_none() {
+                match protocol {
+                    Protocol::V11 => response.headers.insert("Transfer-Encoding", "chunked"),
+                    _ => keep_alive = false
+                }
+            }
+
+            if !keep_alive {
+                response.headers.insert("Connection", "close");
+            } else if protocol == Protocol::V10 {
+                response.headers.insert("Connection", "keep-alive");
+            }
+
+            match head_only {
+                true => response.write_head(protocol, &mut writer)?,
+                false => response.write_to(protocol, &mut writer)?
+            }
+            writer.flush()?;
+
+            if !connection.idle() || !keep_alive {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn respond(&self, request: Request) -> Response {
+        Next::new(&self.middleware, &|request| self.dispatch(request)).run(request)
+    }
+
+    fn dispatch(&self, mut request: Request) -> Response {
+
+        match self.routes.find(&request.method, &request.path) {
+            Match::Found(route, params, middleware) => {
+                // segments are matched as sent, captured values are handed out decoded
+                request.path_params = params
+                    .into_iter()
+                    .map(|(name, value)| match percent_decode(&value) {
+                        Some(bytes) => (name, String::from_utf8_lossy(&bytes).into_owned()),
+                        None => (name, value)
+                    })
+                    .collect();
+                Next::new(&middleware, &**route).run(request)
+            }
+            Match::MethodNotAllowed(allowed) if request.method == Method::Options => {
+                Response::new(StatusCode::Http200Ok).with_header("Allow", &allow(allowed))
+            }
+            Match::MethodNotAllowed(allowed) => Response::error(StatusCode::Http405MethodNotAllowed)
+                .with_header("Allow", &allow(allowed)),
+            // the server as a whole
+            Match::NotFound if request.method == Method::Options && request.path == "*" => {
+                let mut methods = self.routes.methods();
+                if self.public.is_some() {
+                    methods.push(Method::Get);
+                }
+                Response::new(StatusCode::Http200Ok).with_header("Allow", &allow(methods))
+            }
+            // GET, HEAD and OPTIONS are always answered, other methods only by routes
+            Match::NotFound if !matches!(request.method, Method::Get | Method::Head | Method::Options)
+                && !self.routes.methods().contains(&request.method) => {
+                Response::error(StatusCode::Http501NotImplemented)
+            }
+            Match::NotFound => match self.public.as_ref() {
+                Some(_) if request.method == Method::Options => {
+                    Response::new(StatusCode::Http200Ok).with_header("Allow", &allow(vec![Method::Get]))
+                }
+                Some(root) if matches!(request.method, Method::Get | Method::Head) => serve(self, root, &request),
+                Some(_) => Response::error(StatusCode::Http405MethodNotAllowed)
+                    .with_header("Allow", &allow(vec![Method::Get])),
+                None => RES_NOT_FOUND
+            }
+        }
+    }
+
+}
+
+/// Version of the status line sent to a client speaking `protocol`.
+///
+/// HTTP/1.0 clients are answered in kind, anyone else with the highest version spoken here.
+fn response_version(protocol: Protocol) -> Protocol {
+    match protocol {
+        Protocol::V10 => Protocol::V10,
+        _ => Protocol::V11
+    }
+}
+
+/// Value of an `Allow` header for `methods`, completed with HEAD for GET and with OPTIONS.
+fn allow(mut methods: Vec<Method>) -> String {
+
+    if methods.contains(&Method::Get) && !methods.contains(&Method::Head) {
+        methods.push(Method::Head);
+    }
+    if !methods.contains(&Method::Options) {
+        methods.push(Method::Options);
+    }
+
+    let mut names: Vec<&str> = vec![];
+
+    for method in &methods {
+        if !names.contains(&method.as_str()) {
+            names.push(method.as_str());
+        }
+    }
+
+    names.join(", ")
+}
+
+impl Content {
+    /// Opens the file at `path` to be streamed as a body.
+    pub fn file(path: &Path) -> std::io::Result<Self> {
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        Ok(Content::File(file, length))
+    }
+
+    /// Bytes of a buffered body, empty for streams and files.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Content::HeapString(string) => string.as_bytes(),
+            Content::StaticString(string) => string.as_bytes(),
+            Content::Raw(data) => data,
+            Content::Stream(..) | Content::File(..) | Content::None => &[]
+        }
+    }
+
+    /// Length of the body if it is known before sending it.
+    pub fn len(&self) -> Option<u64> {
+        match self {
+            Content::Stream(_, length) => *length,
+            Content::File(_, length) => Some(*length),
+            content => Some(content.as_bytes().len() as u64)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+}
+
+/// Copies `reader` to `writer` in pieces of at most [`WRITE_CHUNK_SIZE`] bytes.
+///
+/// With a `length` exactly that many bytes are copied and a shorter reader is an error,
+/// as the peer already got the length in the header block.
+fn copy_body<R: Read + ?Sized, W: Write + ?Sized>(reader: &mut R, writer: &mut W, length: Option<u64>) -> std::io::Result<()> {
+
+    let mut buffer = vec![0; WRITE_CHUNK_SIZE];
+    let mut remaining = length.unwrap_or(u64::MAX);
+
+    while remaining > 0 {
+        let limit = buffer.len().min(usize::try_from(remaining).unwrap_or(usize::MAX));
+
+        let read = match reader.read(&mut buffer[..limit]) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err)
+        };
+
+        writer.write_all(&buffer[..read])?;
+        remaining -= read as u64;
+    }
+
+    match length {
+        Some(_) if remaining > 0 => Err(std::io::ErrorKind::UnexpectedEof.into()),
+        _ => Ok(())
+    }
+}
+
+impl Response {
+
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            body: Content::None,
+            content_type: ContentType::Unknown,
+            status,
+            headers: Headers::new()
         }
     }
 
+    pub fn text(body: impl Into<String>) -> Self {
+        Self::new(StatusCode::Http200Ok).with_body(Content::HeapString(body.into()), ContentType::TextPlain)
+    }
+
+    pub fn html(body: impl Into<String>) -> Self {
+        Self::new(StatusCode::Http200Ok).with_body(Content::HeapString(body.into()), ContentType::TextHtml)
+    }
+
+    pub fn json(value: &json::Json) -> Self {
+        Self::new(StatusCode::Http200Ok).with_body(Content::HeapString(value.to_string()), ContentType::ApplicationJson)
+    }
+
+    /// HTML error page for `status`.
+    pub fn error(status: StatusCode) -> Self {
+        Self::new(status).with_body(Content::HeapString(format!("
+<!DOCTYPE html>
+<html lang=\"en\">
+<head><title>{0}</title></head>
+<body><h1>{0}</h1></body>
+</html>", status.as_str())), ContentType::TextHtml)
+    }
+
+    /// Temporary redirect to `location`.
+    pub fn redirect(location: &str) -> Self {
+        Self::new(StatusCode::Http302MovedTemporarily).with_header("Location", location)
+    }
+
+    /// Permanent redirect to `location`.
+    pub fn moved_permanently(location: &str) -> Self {
+        Self::new(StatusCode::Http301MovedPermanently).with_header("Location", location)
+    }
+
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn with_body(mut self, body: Content, content_type: ContentType) -> Self {
+        self.body = body;
+        self.content_type = content_type;
+        self
+    }
+
+    /// Appends a header field; fields of the same name already present are kept.
+    ///
+    /// A `name` that is not a token or a `value` containing CR, LF or NUL turns the
+    /// response into a 500 once it is written.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Serializes the status line, header block and body for a client speaking `protocol`.
+    ///
+    /// `Content-Length` is computed from the body and a `Content-Type` field in
+    /// `headers` takes precedence over `content_type`. A body of unknown length is
+    /// chunked when `headers` ask for `Transfer-Encoding: chunked` and the client
+    /// speaks HTTP/1.1, otherwise it runs until the connection is closed.
+    ///
+    /// A response with header fields that can not be written as is becomes
+    /// [`RES_SERVER_ERROR`] instead.
+    pub fn write_to<W: Write>(&mut self, protocol: Protocol, stream: &mut W) -> std::io::Result<()> {
+
+        if !self.headers.is_valid() {
+            *self = RES_SERVER_ERROR;
+        }
+
+        self.write_head(protocol, stream)?;
+
+        let length = self.body.len();
+        let chunked = length.is_none()
+            && response_version(protocol) == Protocol::V11
+            && self.headers.has_token("Transfer-Encoding", "chunked");
+
+        match &mut self.body {
+            Content::Stream(reader, _) if chunked => {
+                let mut chunked = ChunkedWriter::new(&mut *stream);
+                copy_body(reader, &mut chunked, None)?;
+                chunked.finish()?;
+                Ok(())
+            }
+            Content::Stream(reader, length) => copy_body(reader, stream, *length),
+            Content::File(file, length) => copy_body(file, stream, Some(*length)),
+            body => stream.write_all(body.as_bytes())
+        }
+    }
+
+    /// Writes the status line and header fields only, as the answer to a HEAD request.
+    ///
+    /// The header fields describe the body as [`Response::write_to`] would send it.
+    pub fn write_head<W: Write>(&self, protocol: Protocol, stream: &mut W) -> std::io::Result<()> {
+
+        // a CR or LF in a field would let it inject fields or a whole response
+        if !self.headers.is_valid() {
+            return RES_SERVER_ERROR.write_head(protocol, stream);
+        }
+
+        let length = self.body.len();
+        let version = response_version(protocol);
+
+        let mut head = format!("{} {}\r\n", version.as_str(), self.status.as_str());
+
+        // these never have a body, a length would describe the unsent representation
+        let bodiless = matches!(
+            self.status,
+            StatusCode::Http100Continue
+            | StatusCode::Http101SwitchingProtocols
+            | StatusCode::Http102Processing
+            | StatusCode::Http103EarlyHints
+            | StatusCode::Http204NoContent
+            | StatusCode::Http304NotModified
+        );
+
+        if let (Some(length), false) = (length, bodiless) {
+            head.push_str(&format!("Content-Length: {}\r\n", length));
+        }
+
+        if !self.headers.contains("Content-Type") && !matches!(self.body, Content::None) {
+            head.push_str(&format!("Content-Type: {}\r\n", self.content_type.as_str()));
+        }
+
+        for (name, value) in self.headers.iter() {
+            // transfer codings do not exist before HTTP/1.1
+            if name.eq_ignore_ascii_case("Content-Length")
+                || name.eq_ignore_ascii_case("Transfer-Encoding") && (length.is_some() || version != Protocol::V11) {
+                continue;
+            }
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+
+        head.push_str("\r\n");
+
+        stream.write_all(head.as_bytes())
+    }
 }
 
 impl Request {
 
-    pub fn from(stream: &mut TcpStream) -> Self {
+    /// Reads one request from `reader`.
+    ///
+    /// [`ReadError::status`] gives the status to answer a request that could not be
+    /// read or parsed with.
+    pub fn from<R: BufRead>(reader: &mut R, limits: &Limits) -> Result<Self, ReadError> {
 
-        let mut buffer = [0; 1024];
-        stream.read(&mut buffer).expect("Error reading stream");
-        let http_request_str = std::str::from_utf8(&buffer).unwrap_or_default();
+        let mut request = Request::from_head(reader, limits)?;
+        request.read_content(reader, limits)?;
 
-        let mut req_iter = http_request_str.split_whitespace();
-        let method_str = req_iter.next().unwrap_or_default();
+        Ok(request)
+    }
+
+    /// Reads the request line and header fields, leaving the body in `reader`.
+    ///
+    /// Until [`Request::read_content`] is called `content_size` holds the announced length.
+    pub fn from_head<R: BufRead>(reader: &mut R, limits: &Limits) -> Result<Self, ReadError> {
 
-        let query = {
+        let head = read_head(reader, limits.max_header_size)?;
 
-            let mut query_str = req_iter
-            .next()
-            .unwrap_or_default()
-            .split("?");
+        let http_request_str = String::from_utf8_lossy(&head);
 
-            (query_str.next().unwrap_or_default(), {
-                let params = query_str.next().unwrap_or_default().split("&");
+        let mut lines = http_request_str.lines();
+        let request_line = lines.next().unwrap_or_default();
+        let mut req_iter = request_line.split(' ');
+        let method_str = req_iter.next().unwrap_or_default();
 
-                let mut result: Vec<(String, String)> = vec![];
+        let target = req_iter.next().unwrap_or_default();
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
 
-                for param in params {
-                    let mut key_val = param.split("=");
+        let version = req_iter.next().unwrap_or_default();
 
-                    result.push((
-                        key_val.next().unwrap_or_default().to_owned(),
-                        key_val.next().unwrap_or_default().to_owned())
-                    );
-                }
+        if request_line.split(' ').count() != 3 {
+            return Err(ReadError::Malformed);
+        }
 
-                result
-            })
+        let protocol = match Protocol::parse(version) {
+            Some(protocol) if protocol.is_supported() => protocol,
+            Some(_) => return Err(ReadError::UnsupportedVersion),
+            None => return Err(ReadError::Malformed)
         };
 
-        let content_size = match http_request_str.find("Content-Length: ") {
-            Some(i) => {
-
-                match http_request_str.get(i + 16..) {
-                    Some(prolog) => prolog
-                        .chars()
-                        .take_while(|&ch| ch != '\r')
-                        .collect::<String>()
-                        .parse::<usize>()
-                        .unwrap_or_default()
-                    ,
-                    None => 0
+        let method = Method::from_str(method_str);
+
+        // the asterisk form only addresses the server as a whole for OPTIONS
+        if method == Method::Unknown || path == "*" && method != Method::Options {
+            return Err(ReadError::Malformed);
+        }
+
+        let mut headers = Headers::new();
+
+        if !parse_fields(&lines.collect::<Vec<_>>().join("\n"), &mut headers) {
+            return Err(ReadError::Malformed);
+        }
+
+        // every Content-Length field has to carry the same plain decimal value
+        let content_size = {
+            let mut lengths = headers.get_list("Content-Length");
+
+            match lengths.next() {
+                Some(value) if value.bytes().all(|byte| byte.is_ascii_digit()) => {
+                    match value.parse::<usize>() {
+                        Ok(length) if lengths.all(|other| other == value) => length,
+                        Ok(_) => return Err(ReadError::Malformed),
+                        // a length that does not even fit in memory
+                        Err(_) => usize::MAX
+                    }
                 }
-            },
-            None => 0
-        };
-        
-        let content = if content_size > 0 {
-            let mut content_buf: Vec<u8> = Vec::with_capacity(content_size);
-            if let Err(err) = stream.take(content_size as u64).read_to_end(&mut content_buf) {
-                println!("Error reading stream. {}", err);
+                Some(_) => return Err(ReadError::Malformed),
+                None => 0
             }
-            content_buf
-        } else {
-            vec![]
         };
 
-        Request {
-            method: Method::from_str(method_str),
-            content,
-            content_type: ContentType::Unknown,
+        let content_type = headers
+            .get("Content-Type")
+            .map(ContentType::from_header)
+            .unwrap_or(ContentType::Unknown);
+
+        match headers.get_list("Transfer-Encoding").collect::<Vec<_>>().as_slice() {
+            [] => (),
+            // a length next to a transfer coding is a request smuggling attempt
+            _ if headers.contains("Content-Length") => return Err(ReadError::Malformed),
+            // HTTP/1.0 has no transfer codings, the body can not be delimited
+            _ if protocol == Protocol::V10 => return Err(ReadError::Malformed),
+            [coding] if coding.eq_ignore_ascii_case("chunked") => (),
+            [.., last] if last.eq_ignore_ascii_case("chunked") => return Err(ReadError::UnsupportedEncoding),
+            _ => return Err(ReadError::Malformed)
+        }
+
+        Ok(Request {
+            method,
+            headers,
+            trailers: Headers::new(),
             content_size,
-            protocol: Protocol::from_str(req_iter.next().unwrap_or_default()),
+            content: vec![],
+            body: None,
+            content_type,
+            protocol,
             status: StatusCode::Http200Ok,
-            path: query.0.to_owned(),
-            params: query.1
+            path: path.to_owned(),
+            query: Query::parse(query),
+            path_params: vec![]
+        })
+    }
+
+    /// Reads the body announced by the header fields read with [`Request::from_head`].
+    pub fn read_content<R: BufRead>(&mut self, reader: &mut R, limits: &Limits) -> Result<(), ReadError> {
+
+        // anything but a lone chunked coding was refused with the header fields
+        let (content, trailers) = match self.headers.contains("Transfer-Encoding") {
+            true => read_chunked(reader, limits)?,
+            false => (read_body(reader, self.content_size, limits.max_body_size)?, Headers::new())
+        };
+
+        self.content_size = content.len();
+        self.content = content;
+        self.trailers = trailers;
+
+        Ok(())
+    }
+
+    /// Whether the client wants the connection to stay open after the response.
+    pub fn keep_alive(&self) -> bool {
+        if self.headers.has_token("Connection", "close") {
+            false
+        } else if self.headers.has_token("Connection", "keep-alive") {
+            true
+        } else {
+            self.protocol == Protocol::V11
         }
     }
-}
\ No newline at end of file
+
+    /// Returns the path parameter `name` captured by the matched route pattern.
+    pub fn path_param(&self, name: &str) -> Option<&str> {
+        self.path_params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the first value of the request header `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// Returns every value of the request header `name`, ignoring case.
+    pub fn header_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers.get_all(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::SocketAddr;
+    use std::thread::{self, JoinHandle};
+
+    /// Runs `server` on a free loopback port until it is shut down.
+    pub fn start(server: Server) -> (SocketAddr, ShutdownHandle, JoinHandle<()>) {
+
+        let server = server.address("127.0.0.1:0");
+        let handle = server.shutdown_handle();
+        let thread = thread::spawn(move || server.run().unwrap());
+
+        // without connecting, a probe connection would take a worker or a queue slot
+        loop {
+            match handle.address() {
+                Some(address) => return (address, handle, thread),
+                None if thread.is_finished() => panic!("the server stopped before listening"),
+                None => thread::sleep(Duration::from_millis(10))
+            }
+        }
+    }
+
+    /// Sends `request` as is and returns everything received until the server closes
+    /// the connection.
+    pub fn exchange(address: SocketAddr, request: &[u8]) -> String {
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(request).unwrap();
+
+        let mut response = vec![];
+        let _ = stream.read_to_end(&mut response);
+
+        String::from_utf8_lossy(&response).into_owned()
+    }
+
+    fn head(request: &str) -> Result<Request, ReadError> {
+        Request::from_head(&mut request.as_bytes(), &Limits::default())
+    }
+
+    #[test]
+    fn parses_the_request_line_and_fields() {
+        let request = head("GET /a/b?x=1&y HTTP/1.1\r\nHost: example.com\r\nX-Empty:\r\nAccept:  text/html \t\r\n\r\n").unwrap();
+
+        assert_eq!(request.method, Method::Get);
+        assert_eq!(request.path, "/a/b");
+        assert_eq!(request.query.get("x"), Some("1"));
+        assert_eq!(request.protocol, Protocol::V11);
+        assert_eq!(request.header("host"), Some("example.com"));
+        assert_eq!(request.header("X-EMPTY"), Some(""));
+        assert_eq!(request.header("Accept"), Some("text/html"));
+        assert_eq!(request.headers.len(), 3);
+        assert_eq!(request.content_size, 0);
+    }
+
+    #[test]
+    fn refuses_malformed_request_lines() {
+        for request in [
+            "GET /\r\n\r\n",
+            "GET  / HTTP/1.1\r\n\r\n",
+            "GET / HTTP/1.1 extra\r\n\r\n",
+            "GET / HTTP/x\r\n\r\n",
+            "G(T / HTTP/1.1\r\n\r\n",
+            "GET * HTTP/1.1\r\n\r\n"
+        ] {
+            assert!(matches!(head(request), Err(ReadError::Malformed)), "{:?}", request);
+        }
+    }
+
+    #[test]
+    fn refuses_malformed_fields() {
+        for request in [
+            // obsolete line folding
+            "GET / HTTP/1.1\r\nX-Long: a\r\n b\r\n\r\n",
+            "GET / HTTP/1.1\r\nX-Long: a\r\n\tb: c\r\n\r\n",
+            "GET / HTTP/1.1\r\nNo colon\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost : x\r\n\r\n",
+            "GET / HTTP/1.1\r\n: x\r\n\r\n"
+        ] {
+            assert!(matches!(head(request), Err(ReadError::Malformed)), "{:?}", request);
+        }
+    }
+
+    #[test]
+    fn checks_content_length() {
+        let length = |fields: &str| head(&format!("POST / HTTP/1.1\r\n{}\r\n", fields)).map(|request| request.content_size);
+
+        assert_eq!(length("Content-Length: 5\r\n").unwrap(), 5);
+        assert_eq!(length("Content-Length: 5\r\ncontent-length: 5\r\n").unwrap(), 5);
+        assert_eq!(length("Content-Length: 5, 5\r\n").unwrap(), 5);
+        assert_eq!(length("Content-Length: 99999999999999999999999\r\n").unwrap(), usize::MAX);
+
+        for fields in [
+            "Content-Length: 5\r\nContent-Length: 6\r\n",
+            "Content-Length: 5, 6\r\n",
+            "Content-Length: +5\r\n",
+            "Content-Length: -1\r\n",
+            "Content-Length: 0x5\r\n",
+            "Content-Length: 5 5\r\n"
+        ] {
+            assert!(matches!(length(fields), Err(ReadError::Malformed)), "{:?}", fields);
+        }
+    }
+
+    #[test]
+    fn refuses_a_length_next_to_a_transfer_coding() {
+        for fields in [
+            "Content-Length: 5\r\nTransfer-Encoding: chunked\r\n",
+            "Transfer-Encoding: chunked\r\nContent-Length: 5\r\n",
+            "Transfer-Encoding: chunked\r\nContent-Length: 0\r\n"
+        ] {
+            let request = format!("POST / HTTP/1.1\r\n{}\r\n", fields);
+            assert!(matches!(head(&request), Err(ReadError::Malformed)), "{:?}", fields);
+        }
+
+        assert!(head("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n").is_ok());
+        assert!(matches!(head("POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n"), Err(ReadError::UnsupportedEncoding)));
+        assert!(matches!(head("POST / HTTP/1.1\r\nTransfer-Encoding: chunked, gzip\r\n\r\n"), Err(ReadError::Malformed)));
+    }
+
+    #[test]
+    fn checks_the_request_version() {
+        let version = |version: &str| head(&format!("GET / {}\r\n\r\n", version));
+
+        assert_eq!(version("HTTP/1.0").unwrap().protocol, Protocol::V10);
+        assert_eq!(version("HTTP/1.1").unwrap().protocol, Protocol::V11);
+
+        for unsupported in ["HTTP/2.0", "HTTP/3.0", "HTTP/0.9"] {
+            let err = version(unsupported).unwrap_err();
+            assert_eq!(err.status(), StatusCode::Http505HttpVersionNotSupported, "{}", unsupported);
+        }
+        for malformed in ["http/1.1", "HTTP/1.10", "HTTP/11.0"] {
+            assert!(matches!(version(malformed), Err(ReadError::Malformed)), "{}", malformed);
+        }
+    }
+
+    #[test]
+    fn answers_in_the_version_of_the_request() {
+        for (protocol, status_line) in [
+            (Protocol::V10, "HTTP/1.0 200 OK\r\n"),
+            (Protocol::V11, "HTTP/1.1 200 OK\r\n"),
+            (Protocol::V20, "HTTP/1.1 200 OK\r\n"),
+            (Protocol::Unknown, "HTTP/1.1 200 OK\r\n")
+        ] {
+            let mut output = vec![];
+            Response::text("x").write_to(protocol, &mut output).unwrap();
+            assert!(output.starts_with(status_line.as_bytes()), "{:?}", protocol);
+        }
+
+        let routes = Router::new().get("/", |_: Request| Response::text("hello"));
+        let (address, handle, server) = start(Server::new().max_connections(2).routes(routes));
+
+        let response = exchange(address, b"GET / HTTP/1.0\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.0 200 OK\r\n"), "{}", response);
+        assert!(!response.contains("Connection: keep-alive"), "{}", response);
+
+        // later HTTP/1 minor versions are answered with HTTP/1.1
+        let response = exchange(address, b"GET / HTTP/1.5\r\nConnection: close\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+
+        let response = exchange(address, b"GET / HTTP/2.0\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 505 HTTP Version Not Supported\r\n"), "{}", response);
+
+        handle.shutdown();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn times_out_incomplete_requests() {
+        let server = Server::new().max_connections(2).header_timeout(Duration::from_millis(300));
+        let (address, handle, thread) = start(server);
+
+        let response = exchange(address, b"GET / HTTP/1.1\r\nHost:");
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"), "{:?}", response);
+        assert!(response.contains("Connection: close\r\n"), "{:?}", response);
+
+        // the deadline covers the whole header block, not each read
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        for part in ["GET / HTTP/1.1\r\n", "Host: x\r\n", "Accept: */*\r\n"] {
+            stream.write_all(part.as_bytes()).unwrap();
+            thread::sleep(Duration::from_millis(100));
+        }
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"), "{:?}", response);
+
+        // nothing to answer without a single byte of a request
+        assert_eq!(exchange(address, b""), "");
+
+        handle.shutdown();
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn answers_500_when_a_handler_panics() {
+        let routes = Router::new()
+            .get("/panic", |_: Request| -> Response { panic!("handler failed") })
+            .get("/", |_: Request| Response::text("fine"));
+        let (address, handle, server) = start(Server::new().max_connections(1).routes(routes));
+
+        // the connection goes on after the failed request, and so does the only worker
+        let response = exchange(address, b"GET /panic HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"), "{}", response);
+        assert!(response.ends_with("fine"), "{}", response);
+
+        let response = exchange(address, b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+
+        handle.shutdown();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn sheds_connections_beyond_the_queue() {
+        let routes = Router::new().get("/slow", |_: Request| {
+            thread::sleep(Duration::from_millis(400));
+            Response::text("done")
+        });
+        let server = Server::new().max_connections(1).queue(1, QueuePolicy::Reject).routes(routes);
+        let (address, handle, thread) = start(server);
+
+        let request = b"GET /slow HTTP/1.1\r\nConnection: close\r\n\r\n";
+        let working = thread::spawn(move || exchange(address, request));
+        thread::sleep(Duration::from_millis(100));
+        let queued = thread::spawn(move || exchange(address, request));
+        thread::sleep(Duration::from_millis(100));
+
+        let response = exchange(address, request);
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"), "{}", response);
+        assert!(response.contains("Retry-After: 1\r\n"), "{}", response);
+        assert!(response.contains("Connection: close\r\n"), "{}", response);
+
+        for client in [working, queued] {
+            assert!(client.join().unwrap().ends_with("done"));
+        }
+
+        handle.shutdown();
+        thread.join().unwrap();
+    }
+
+    fn respond(server: &Server, request: &str) -> Response {
+        server.respond(head(request).unwrap())
+    }
+
+    #[test]
+    fn answers_extension_methods_only_with_routes() {
+        let purge = Method::from_str("PURGE");
+        let server = Server::new().routes(Router::new().route(purge.clone(), "/cache", |_: Request| Response::text("purged")));
+
+        assert_eq!(respond(&server, "PURGE /cache HTTP/1.1\r\n\r\n").body.as_bytes(), b"purged");
+        assert_eq!(respond(&server, "PURGE /other HTTP/1.1\r\n\r\n").status, StatusCode::Http404NotFound);
+        assert_eq!(respond(&server, "BREW /cache HTTP/1.1\r\n\r\n").status, StatusCode::Http405MethodNotAllowed);
+        assert_eq!(respond(&server, "BREW /other HTTP/1.1\r\n\r\n").status, StatusCode::Http501NotImplemented);
+        assert_eq!(respond(&server, "DELETE /other HTTP/1.1\r\n\r\n").status, StatusCode::Http501NotImplemented);
+        assert_eq!(respond(&server, "GET /other HTTP/1.1\r\n\r\n").status, StatusCode::Http404NotFound);
+
+        let response = respond(&server, "OPTIONS /cache HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, StatusCode::Http200Ok);
+        assert_eq!(response.headers.get("Allow"), Some("PURGE, OPTIONS"));
+    }
+
+    #[test]
+    fn answers_options_for_the_whole_server() {
+        let request = "OPTIONS * HTTP/1.1\r\n\r\n";
+        let allow = |server: &Server| respond(server, request).headers.get("Allow").map(str::to_owned);
+        let routes = || Router::new().post("/a", |_: Request| Response::text("a")).delete("/b", |_: Request| Response::text("b"));
+
+        assert_eq!(allow(&Server::new()), Some("OPTIONS".to_owned()));
+        assert_eq!(allow(&Server::new().routes(routes())), Some("POST, DELETE, OPTIONS".to_owned()));
+        assert_eq!(allow(&Server::new().public("public")), Some("GET, HEAD, OPTIONS".to_owned()));
+        assert_eq!(allow(&Server::new().public("public").routes(routes())), Some("POST, DELETE, GET, HEAD, OPTIONS".to_owned()));
+
+        // the static files only know GET, a method no route uses is not implemented at all
+        let server = Server::new().public("public");
+        assert_eq!(respond(&server, "PUT /index.html HTTP/1.1\r\n\r\n").status, StatusCode::Http501NotImplemented);
+
+        let server = server.routes(Router::new().put("/upload", |_: Request| Response::text("stored")));
+        let response = respond(&server, "PUT /index.html HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, StatusCode::Http405MethodNotAllowed);
+        assert_eq!(response.headers.get("Allow"), Some("GET, HEAD, OPTIONS"));
+    }
+
+    #[test]
+    fn leaves_out_the_body_of_head_responses() {
+        let routes = Router::new().get("/", |_: Request| Response::text("hello"));
+        let (address, handle, server) = start(Server::new().max_connections(2).routes(routes));
+
+        let response = exchange(address, b"HEAD / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+        // the second response starts right after the header block of the first
+        let (head, get) = response.split_at(response[1..].find("HTTP/1.1 ").unwrap() + 1);
+
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(head.contains("Content-Length: 5\r\n"), "{}", response);
+        assert!(head.ends_with("\r\n\r\n"), "{}", response);
+        assert!(get.ends_with("\r\n\r\nhello"), "{}", response);
+
+        handle.shutdown();
+        server.join().unwrap();
+    }
+
+    fn written(mut response: Response, head_only: bool) -> String {
+        let mut output = vec![];
+
+        match head_only {
+            true => response.write_head(Protocol::V11, &mut output).unwrap(),
+            false => response.write_to(Protocol::V11, &mut output).unwrap()
+        }
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn writes_header_fields() {
+        let output = written(Response::redirect("/next?a=1").with_header("X-Token", "a b\tc"), false);
+
+        assert!(output.starts_with("HTTP/1.1 302 Moved Temporarily\r\n"), "{}", output);
+        assert!(output.contains("\r\nLocation: /next?a=1\r\n"));
+        assert!(output.contains("\r\nX-Token: a b\tc\r\n"));
+    }
+
+    #[test]
+    fn refuses_to_inject_header_fields() {
+        let injected = || [
+            Response::redirect("/\r\nSet-Cookie: a=b"),
+            Response::text("x").with_header("X-Value", "a\nSet-Cookie: a=b"),
+            Response::text("x").with_header("X-Value", "a\0b"),
+            Response::text("x").with_header("Set-Cookie: a=b\r\nX", "c"),
+            Response::text("x").with_header("Bad Name", "c"),
+            Response::text("x").with_header("", "c")
+        ];
+
+        for head_only in [false, true] {
+            for response in injected() {
+                let output = written(response, head_only);
+
+                assert!(output.starts_with("HTTP/1.1 500 Internal Server Error\r\n"), "{}", output);
+                assert!(!output.contains("Set-Cookie"), "{}", output);
+                assert!(!output.contains("X-Value"), "{}", output);
+            }
+        }
+    }
+}
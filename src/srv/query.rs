@@ -0,0 +1,209 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Decoded `name=value` pairs of a query string, in the order they were sent.
+///
+/// ```ignore
+/// // GET /search?q=hello%20world&tag=a+b&tag=c&page=2
+/// let query = &request.query;
+///
+/// assert_eq!(query.get("q"), Some("hello world"));
+/// assert_eq!(query.get_all("tag").collect::<Vec<_>>(), ["a b", "c"]);
+/// assert_eq!(query.get_as::<u32>("page"), Some(Ok(2)));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    pairs: Vec<(String, String)>,
+}
+
+impl Query {
+
+    pub const fn new() -> Self {
+        Self { pairs: vec![] }
+    }
+
+    /// Parses `application/x-www-form-urlencoded` pairs, the part of a target after `?`.
+    ///
+    /// `+` stands for a space and `%XX` escapes are decoded, a name or value with an
+    /// invalid escape is kept as sent. Empty pairs are skipped and a name without `=`
+    /// has an empty value.
+    pub fn parse(query: &str) -> Self {
+
+        let pairs = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode_component(name), decode_component(value))
+            })
+            .collect();
+
+        Self { pairs }
+    }
+
+    /// First value of `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// All values of `name`, in the order they were sent.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.pairs
+            .iter()
+            .filter(move |(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// First value of `name` parsed as `T`, `None` if there is no value.
+    pub fn get_as<T: FromStr>(&self, name: &str) -> Option<Result<T, T::Err>> {
+        self.get(name).map(str::parse)
+    }
+
+    /// First value of `name` parsed as `T`, `default` if it is missing or does not parse.
+    pub fn get_or<T: FromStr>(&self, name: &str, default: T) -> T {
+        self.get_as(name).and_then(Result::ok).unwrap_or(default)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.pairs.push((name.to_owned(), value.to_owned()));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+/// Encodes the pairs back into a query string, without the leading `?`.
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (name, value)) in self.pairs.iter().enumerate() {
+            if index > 0 {
+                f.write_str("&")?;
+            }
+            write!(f, "{}={}", percent_encode(name), percent_encode(value))?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes `%XX` escapes, returns `None` for a truncated or non-hexadecimal escape.
+pub fn percent_decode(input: &str) -> Option<Vec<u8>> {
+
+    let bytes = input.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        match bytes[index] {
+            b'%' => {
+                let hex = std::str::from_utf8(bytes.get(index + 1..index + 3)?).ok()?;
+                if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+                    return None;
+                }
+                result.push(u8::from_str_radix(hex, 16).ok()?);
+                index += 3;
+            }
+            byte => {
+                result.push(byte);
+                index += 1;
+            }
+        }
+    }
+
+    Some(result)
+}
+
+/// Escapes every byte outside the RFC 3986 unreserved set.
+pub fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte)
+        })
+        .collect()
+}
+
+/// Decodes one form-encoded name or value, invalid UTF-8 is replaced.
+fn decode_component(raw: &str) -> String {
+
+    let raw = raw.replace('+', " ");
+
+    match percent_decode(&raw) {
+        Some(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        None => raw
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_decodes_pairs() {
+        let query = Query::parse("q=hello%20world&tag=a+b&tag=c&page=2&flag&=empty&&caf%C3%A9=%E2%9C%93");
+
+        assert_eq!(query.get("q"), Some("hello world"));
+        assert_eq!(query.get_all("tag").collect::<Vec<_>>(), ["a b", "c"]);
+        assert_eq!(query.get("flag"), Some(""));
+        assert_eq!(query.get(""), Some("empty"));
+        assert_eq!(query.get("café"), Some("✓"));
+        assert_eq!(query.get("missing"), None);
+        assert_eq!(query.len(), 7);
+    }
+
+    #[test]
+    fn keeps_invalid_escapes_as_sent() {
+        let query = Query::parse("a=100%&b=%zz&c=%2&d=%ff");
+
+        assert_eq!(query.get("a"), Some("100%"));
+        assert_eq!(query.get("b"), Some("%zz"));
+        assert_eq!(query.get("c"), Some("%2"));
+        assert_eq!(query.get("d"), Some("\u{fffd}"));
+    }
+
+    #[test]
+    fn converts_values() {
+        let query = Query::parse("page=2&size=big");
+
+        assert_eq!(query.get_as::<u32>("page"), Some(Ok(2)));
+        assert!(matches!(query.get_as::<u32>("size"), Some(Err(_))));
+        assert_eq!(query.get_as::<u32>("missing"), None);
+        assert_eq!(query.get_or("size", 10), 10);
+        assert!(query.contains("size") && !query.contains("sizes"));
+        assert!(Query::parse("").is_empty());
+    }
+
+    #[test]
+    fn display_encodes_round_trip() {
+        let mut query = Query::new();
+        query.append("a b", "x&y=z");
+        query.append("path", "/ü/+");
+
+        assert_eq!(query.to_string(), "a%20b=x%26y%3Dz&path=%2F%C3%BC%2F%2B");
+        assert_eq!(Query::parse(&query.to_string()), query);
+    }
+
+    #[test]
+    fn percent_codes() {
+        assert_eq!(percent_decode("a%2Fb%2fc+d"), Some(b"a/b/c+d".to_vec()));
+        assert_eq!(percent_decode("%"), None);
+        assert_eq!(percent_decode("%g0"), None);
+        assert_eq!(percent_decode("%+1"), None);
+        assert_eq!(percent_encode("AZaz09-._~ !é"), "AZaz09-._~%20%21%C3%A9");
+    }
+}
@@ -0,0 +1,157 @@
+/// Most ranges served from one request, more are answered with the whole file.
+pub const MAX_RANGES: usize = 16;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Ranges {
+    /// No usable range, the whole representation is sent.
+    Full,
+    /// Satisfiable ranges as inclusive `(first, last)` byte positions in request order,
+    /// or in ascending order once overlapping or adjacent ones were merged.
+    Partial(Vec<(u64, u64)>),
+    /// None of the requested ranges overlaps the representation.
+    Unsatisfiable,
+}
+
+/// Interprets a `Range` field value for a representation of `length` bytes.
+///
+/// Unknown units, syntax errors and too many ranges make the field ignored.
+pub fn parse(value: &str, length: u64) -> Ranges {
+
+    let specs = match value.trim().split_once('=') {
+        Some((unit, specs)) if unit.trim().eq_ignore_ascii_case("bytes") => specs,
+        _ => return Ranges::Full
+    };
+
+    let mut ranges = vec![];
+    let mut count = 0;
+
+    for spec in specs.split(',').map(str::trim).filter(|spec| !spec.is_empty()) {
+
+        count += 1;
+        if count > MAX_RANGES {
+            return Ranges::Full;
+        }
+
+        let (first, last) = match spec.split_once('-') {
+            Some(bounds) => bounds,
+            None => return Ranges::Full
+        };
+
+        let (first, last) = match (parse_position(first), parse_position(last)) {
+            // bytes=-500, the final 500 bytes
+            (None, Some(suffix)) if first.is_empty() => {
+                if suffix == 0 || length == 0 {
+                    continue;
+                }
+                (length.saturating_sub(suffix), length - 1)
+            }
+            // bytes=9500-
+            (Some(first), None) if last.is_empty() => (first, u64::MAX),
+            (Some(first), Some(last)) if first <= last => (first, last),
+            _ => return Ranges::Full
+        };
+
+        if first < length {
+            ranges.push((first, last.min(length - 1)));
+        }
+    }
+
+    match (count, ranges.is_empty()) {
+        (0, _) => Ranges::Full,
+        (_, true) => Ranges::Unsatisfiable,
+        (_, false) => Ranges::Partial(coalesce(ranges))
+    }
+}
+
+/// Merges overlapping and adjacent ranges, so no byte is sent more than once.
+fn coalesce(mut ranges: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+
+    let touching = |(first, last): (u64, u64), (other_first, other_last): (u64, u64)| {
+        first <= other_last.saturating_add(1) && other_first <= last.saturating_add(1)
+    };
+
+    let merge = ranges
+        .iter()
+        .enumerate()
+        .any(|(index, range)| ranges[index + 1..].iter().any(|other| touching(*range, *other)));
+
+    if !merge {
+        return ranges;
+    }
+
+    ranges.sort_unstable();
+
+    let mut merged: Vec<(u64, u64)> = vec![];
+
+    for (first, last) in ranges {
+        match merged.last_mut() {
+            Some(previous) if touching(*previous, (first, last)) => previous.1 = previous.1.max(last),
+            _ => merged.push((first, last))
+        }
+    }
+
+    merged
+}
+
+fn parse_position(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_ranges() {
+        assert_eq!(parse("bytes=0-499", 10000), Ranges::Partial(vec![(0, 499)]));
+        assert_eq!(parse("bytes=9500-", 10000), Ranges::Partial(vec![(9500, 9999)]));
+        assert_eq!(parse("bytes=-500", 10000), Ranges::Partial(vec![(9500, 9999)]));
+        assert_eq!(parse("BYTES = 5-5", 10), Ranges::Partial(vec![(5, 5)]));
+    }
+
+    #[test]
+    fn clamps_to_the_representation() {
+        assert_eq!(parse("bytes=5-100", 10), Ranges::Partial(vec![(5, 9)]));
+        assert_eq!(parse("bytes=-100", 10), Ranges::Partial(vec![(0, 9)]));
+    }
+
+    #[test]
+    fn keeps_multiple_ranges_in_request_order() {
+        assert_eq!(parse("bytes=500-599, 0-99,,-1", 1000), Ranges::Partial(vec![(500, 599), (0, 99), (999, 999)]));
+        // ranges past the end are dropped as long as one is satisfiable
+        assert_eq!(parse("bytes=2000-3000,0-0", 1000), Ranges::Partial(vec![(0, 0)]));
+    }
+
+    #[test]
+    fn merges_overlapping_and_adjacent_ranges() {
+        assert_eq!(parse("bytes=0-,0-,0-,0-,0-,0-,0-,0-", 1000), Ranges::Partial(vec![(0, 999)]));
+        assert_eq!(parse("bytes=0-9,10-19", 1000), Ranges::Partial(vec![(0, 19)]));
+        assert_eq!(parse("bytes=500-599,0-9,5-20,-1", 1000), Ranges::Partial(vec![(0, 20), (500, 599), (999, 999)]));
+        assert_eq!(parse("bytes=-100,950-960", 1000), Ranges::Partial(vec![(900, 999)]));
+        assert_eq!(parse("bytes=0-0,2-2,1-1", 1000), Ranges::Partial(vec![(0, 2)]));
+        // a gap keeps them apart
+        assert_eq!(parse("bytes=0-9,11-19", 1000), Ranges::Partial(vec![(0, 9), (11, 19)]));
+    }
+
+    #[test]
+    fn reports_unsatisfiable_ranges() {
+        assert_eq!(parse("bytes=1000-", 1000), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=-0", 1000), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=0-0", 0), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=-5", 0), Ranges::Unsatisfiable);
+    }
+
+    #[test]
+    fn ignores_invalid_fields() {
+        for value in ["", "bytes", "bytes=", "items=0-1", "bytes=1", "bytes=5-1", "bytes=a-b", "bytes=-", "bytes=+1-2", "bytes=0-1,x"] {
+            assert_eq!(parse(value, 1000), Ranges::Full, "{:?}", value);
+        }
+
+        let many = vec!["0-0"; MAX_RANGES + 1].join(",");
+        assert_eq!(parse(&format!("bytes={}", many), 1000), Ranges::Full);
+    }
+}
@@ -0,0 +1,469 @@
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::http::{is_token, Headers, StatusCode};
+
+/// Default upper bound for the request line plus header block.
+pub const DEFAULT_MAX_HEADER_SIZE: usize = 8 * 1024;
+
+/// Default upper bound for a request body.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 8 * 1024 * 1024;
+
+/// Default upper bound for a request body streamed to the handler instead of buffered.
+pub const DEFAULT_MAX_UPLOAD_SIZE: usize = 1024 * 1024 * 1024;
+
+/// Empty lines tolerated before the request line, RFC 9112 asks to ignore at least one.
+const MAX_LEADING_EMPTY_LINES: usize = 4;
+
+/// Size limits applied while reading a request.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_header_size: usize,
+    pub max_body_size: usize,
+    pub max_upload_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_header_size: DEFAULT_MAX_HEADER_SIZE,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_upload_size: DEFAULT_MAX_UPLOAD_SIZE,
+        }
+    }
+}
+
+/// Reading side of a connection, with the number of body bytes a handler has yet to read.
+pub struct Inbound {
+    pub reader: BufReader<DeadlineStream>,
+    pub unread: u64,
+}
+
+/// Request body left on the connection for the handler to read as it arrives.
+pub struct Body {
+    inbound: Arc<Mutex<Inbound>>,
+}
+
+impl Body {
+    pub fn new(inbound: Arc<Mutex<Inbound>>) -> Self {
+        Self { inbound }
+    }
+
+    /// Number of body bytes not read yet.
+    pub fn remaining(&self) -> u64 {
+        self.inbound.lock().unwrap().unread
+    }
+}
+
+impl Read for Body {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+
+        let mut inbound = self.inbound.lock().unwrap();
+        let limit = buf.len().min(usize::try_from(inbound.unread).unwrap_or(usize::MAX));
+
+        if limit == 0 {
+            return Ok(0);
+        }
+
+        let read = inbound.reader.read(&mut buf[..limit])?;
+
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        inbound.unread -= read as u64;
+
+        Ok(read)
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Body").field("remaining", &self.remaining()).finish()
+    }
+}
+
+/// Connection whose reads fail with `TimedOut` once a deadline has passed.
+///
+/// A socket read timeout only bounds a single read, so a client trickling one byte at a
+/// time could otherwise hold the connection forever.
+pub struct DeadlineStream {
+    stream: TcpStream,
+    deadline: Option<Instant>,
+    idle: Option<Duration>,
+}
+
+impl DeadlineStream {
+
+    pub fn new(stream: TcpStream) -> Self {
+        Self { stream, deadline: None, idle: None }
+    }
+
+    /// Lets reads continue for `timeout` from now, a zero duration removes the deadline.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.idle = None;
+        self.deadline = match timeout.is_zero() {
+            true => None,
+            false => Some(Instant::now() + timeout)
+        };
+    }
+
+    /// Replaces the deadline with a limit on each read, for bodies that may take long
+    /// to arrive but must keep arriving. A zero duration removes the limit.
+    pub fn set_idle_timeout(&mut self, timeout: Duration) {
+        self.deadline = None;
+        self.idle = match timeout.is_zero() {
+            true => None,
+            false => Some(timeout)
+        };
+    }
+
+    pub fn get_ref(&self) -> &TcpStream {
+        &self.stream
+    }
+}
+
+impl Read for DeadlineStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+
+        let remaining = match self.deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if !remaining.is_zero() => Some(remaining),
+                _ => return Err(io::ErrorKind::TimedOut.into())
+            },
+            None => self.idle
+        };
+
+        self.stream.set_read_timeout(remaining)?;
+
+        // an expired socket timeout is reported as WouldBlock on some platforms
+        match self.stream.read(buf) {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Err(io::ErrorKind::TimedOut.into()),
+            result => result
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReadError {
+    /// The connection ended before a complete request was received.
+    Closed,
+    /// The client took too long to send the request.
+    TimedOut,
+    HeaderTooLarge,
+    BodyTooLarge,
+    /// The message framing could not be parsed.
+    Malformed,
+    /// The body uses a transfer coding the server does not implement.
+    UnsupportedEncoding,
+    /// The request is of an HTTP version the server does not speak.
+    UnsupportedVersion,
+    Io(io::Error),
+}
+
+impl ReadError {
+    /// Status code to answer the client with.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ReadError::HeaderTooLarge => StatusCode::Http431RequestHeaderFieldsTooLarge,
+            ReadError::BodyTooLarge => StatusCode::Http413PayloadToolarge,
+            ReadError::UnsupportedEncoding => StatusCode::Http501NotImplemented,
+            ReadError::TimedOut => StatusCode::Http408RequestTimeout,
+            ReadError::UnsupportedVersion => StatusCode::Http505HttpVersionNotSupported,
+            ReadError::Closed | ReadError::Malformed | ReadError::Io(_) => StatusCode::Http400BadRequest,
+        }
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => ReadError::Closed,
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ReadError::TimedOut,
+            _ => ReadError::Io(err)
+        }
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Closed => write!(f, "connection closed before the request was complete"),
+            ReadError::TimedOut => write!(f, "timed out reading the request"),
+            ReadError::HeaderTooLarge => write!(f, "request header too large"),
+            ReadError::BodyTooLarge => write!(f, "request body too large"),
+            ReadError::Malformed => write!(f, "malformed request"),
+            ReadError::UnsupportedEncoding => write!(f, "unsupported transfer coding"),
+            ReadError::UnsupportedVersion => write!(f, "unsupported HTTP version"),
+            ReadError::Io(err) => write!(f, "error reading the request: {}", err)
+        }
+    }
+}
+
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadError::Io(err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+/// Accumulates the request line and header fields up to the empty line that ends them.
+///
+/// Bytes following the header block stay in `reader`, so a body or a pipelined
+/// request that arrived in the same segment is not lost. A few empty lines before the
+/// request line are skipped, the terminating empty line is not returned.
+pub fn read_head<R: BufRead>(reader: &mut R, limit: usize) -> Result<Vec<u8>, ReadError> {
+
+    let mut head: Vec<u8> = vec![];
+
+    for skipped in 0.. {
+        read_line(reader, &mut head, limit)?;
+
+        if !head.trim_ascii().is_empty() {
+            break;
+        }
+        if skipped == MAX_LEADING_EMPTY_LINES {
+            return Err(ReadError::Malformed);
+        }
+        head.clear();
+    }
+
+    read_fields(reader, &mut head, limit)?;
+
+    Ok(head)
+}
+
+/// Appends field lines to `block` up to and excluding the empty line that ends them.
+pub fn read_fields<R: BufRead>(reader: &mut R, block: &mut Vec<u8>, limit: usize) -> Result<(), ReadError> {
+
+    loop {
+        let start = block.len();
+
+        read_line(reader, block, limit)?;
+
+        if block[start..].trim_ascii().is_empty() {
+            block.truncate(start);
+            return Ok(());
+        }
+    }
+}
+
+/// Appends one line including its terminator to `buffer`, which may grow up to `limit` bytes.
+pub fn read_line<R: BufRead>(reader: &mut R, buffer: &mut Vec<u8>, limit: usize) -> Result<(), ReadError> {
+
+    let remaining = limit.saturating_sub(buffer.len()) as u64;
+
+    let read = reader.by_ref().take(remaining).read_until(b'\n', buffer)?;
+
+    // the buffer may already end with the previous line when nothing was read
+    match read > 0 && buffer.ends_with(b"\n") {
+        true => Ok(()),
+        false if buffer.len() >= limit => Err(ReadError::HeaderTooLarge),
+        false => Err(ReadError::Closed)
+    }
+}
+
+/// Parses `name: value` lines into `headers`, returns `false` on a malformed line.
+///
+/// Obsolete line folding is treated as malformed.
+pub fn parse_fields(block: &str, headers: &mut Headers) -> bool {
+
+    for line in block.lines() {
+        match line.split_once(':') {
+            Some((name, value)) if is_token(name) => {
+                headers.append(name, value.trim_matches([' ', '\t']));
+            }
+            _ => return false
+        }
+    }
+
+    true
+}
+
+/// Reads exactly `length` body bytes, refusing bodies larger than `limit`.
+pub fn read_body<R: BufRead>(reader: &mut R, length: usize, limit: usize) -> Result<Vec<u8>, ReadError> {
+
+    if length > limit {
+        return Err(ReadError::BodyTooLarge);
+    }
+
+    let mut content = vec![0; length];
+    reader.read_exact(&mut content)?;
+
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUEST: &[u8] = b"POST /upload HTTP/1.1\r\nHost: x\r\nContent-Length: 11\r\n\r\nhello world";
+
+    /// Hands out its data in pieces of the given sizes, like segments arriving on a socket.
+    struct Segments<'a>(&'a [u8], Vec<usize>);
+
+    impl Read for Segments<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let size = match self.1.is_empty() {
+                true => self.0.len(),
+                false => self.1.remove(0)
+            };
+            let count = size.min(buf.len()).min(self.0.len());
+            self.0.read(&mut buf[..count])
+        }
+    }
+
+    /// Server side of a loopback connection and the client writing to it.
+    fn connection() -> (DeadlineStream, TcpStream) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        (DeadlineStream::new(listener.accept().unwrap().0), client)
+    }
+
+    /// Writes a byte every `interval` until `count` were written, then keeps the
+    /// connection open a while longer.
+    fn trickle(mut client: TcpStream, count: usize, interval: Duration) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || {
+            for _ in 0..count {
+                let _ = io::Write::write_all(&mut client, b"x");
+                std::thread::sleep(interval);
+            }
+            std::thread::sleep(Duration::from_secs(1));
+        })
+    }
+
+    /// Reads until an error, returning it with the number of bytes read before.
+    fn read_until_error(stream: &mut DeadlineStream) -> (io::Error, usize) {
+        let mut total = 0;
+        loop {
+            match stream.read(&mut [0; 16]) {
+                Ok(read) => total += read,
+                Err(err) => return (err, total)
+            }
+        }
+    }
+
+    #[test]
+    fn stops_reading_at_the_deadline() {
+        let (mut stream, client) = connection();
+        let started = Instant::now();
+        stream.set_timeout(Duration::from_millis(300));
+
+        // every single read is quick, the total is not
+        let writer = trickle(client, 20, Duration::from_millis(50));
+        let (err, read) = read_until_error(&mut stream);
+
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(read > 0 && read < 20, "{}", read);
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert!(started.elapsed() < Duration::from_millis(900));
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn stops_reading_once_idle() {
+        let (mut stream, client) = connection();
+        let started = Instant::now();
+        stream.set_idle_timeout(Duration::from_millis(200));
+
+        // keeps arriving for longer than the idle timeout, then stops
+        let writer = trickle(client, 10, Duration::from_millis(50));
+        let (err, read) = read_until_error(&mut stream);
+
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(read, 10);
+        assert!(started.elapsed() >= Duration::from_millis(650));
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn replaces_one_timeout_with_the_other() {
+        let (mut stream, mut client) = connection();
+        io::Write::write_all(&mut client, b"abc").unwrap();
+
+        stream.set_timeout(Duration::from_millis(1));
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(stream.read(&mut [0; 1]).unwrap_err().kind(), io::ErrorKind::TimedOut);
+
+        // the expired deadline no longer applies
+        stream.set_idle_timeout(Duration::from_secs(1));
+        assert_eq!(stream.read(&mut [0; 1]).unwrap(), 1);
+        assert!(stream.deadline.is_none());
+
+        stream.set_timeout(Duration::from_secs(1));
+        assert!(stream.idle.is_none() && stream.deadline.is_some());
+        assert_eq!(stream.read(&mut [0; 1]).unwrap(), 1);
+
+        // zero removes either limit
+        stream.set_timeout(Duration::ZERO);
+        assert!(stream.idle.is_none() && stream.deadline.is_none());
+        stream.set_idle_timeout(Duration::ZERO);
+        assert!(stream.idle.is_none() && stream.deadline.is_none());
+        assert_eq!(stream.read(&mut [0; 1]).unwrap(), 1);
+    }
+
+    fn head(data: &[u8], limit: usize) -> Result<Vec<u8>, ReadError> {
+        read_head(&mut BufReader::new(data), limit)
+    }
+
+    #[test]
+    fn reads_a_header_block_up_to_the_limit() {
+        let data = b"GET / HTTP/1.1\r\nHost: x\r\n\r\n";
+
+        assert_eq!(head(data, data.len()).unwrap(), b"GET / HTTP/1.1\r\nHost: x\r\n");
+        assert!(matches!(head(data, data.len() - 1), Err(ReadError::HeaderTooLarge)));
+
+        let limits = Limits { max_header_size: data.len() - 1, ..Limits::default() };
+        let err = super::super::Request::from(&mut data.as_slice(), &limits).unwrap_err();
+        assert_eq!(err.status(), StatusCode::Http431RequestHeaderFieldsTooLarge);
+    }
+
+    #[test]
+    fn skips_a_few_empty_lines_before_the_request_line() {
+        assert_eq!(head(b"\r\n\r\nGET / HTTP/1.1\r\n\r\n", 1024).unwrap(), b"GET / HTTP/1.1\r\n");
+        assert_eq!(head(b"\n\r\n\r\n\r\nGET / HTTP/1.1\r\n\r\n", 1024).unwrap(), b"GET / HTTP/1.1\r\n");
+        assert!(matches!(head(&b"\r\n".repeat(5 + 1), 1024), Err(ReadError::Malformed)));
+        assert!(matches!(head(&b"\r\n".repeat(1000), 1024), Err(ReadError::Malformed)));
+    }
+
+    #[test]
+    fn reports_incomplete_header_blocks() {
+        assert!(matches!(head(b"", 1024), Err(ReadError::Closed)));
+        assert!(matches!(head(b"GET / HTTP/1.1\r\nHost: x\r\n", 1024), Err(ReadError::Closed)));
+    }
+
+    #[test]
+    fn keeps_body_bytes_read_with_the_head() {
+        // split inside the header block, at its end and inside the body
+        for sizes in [vec![10, 40, 3], vec![REQUEST.len() - 11, 5], vec![REQUEST.len() - 6], vec![1; REQUEST.len()]] {
+            let mut data = REQUEST.to_vec();
+            data.extend_from_slice(b"GET /next HTTP/1.1\r\n\r\n");
+
+            let mut reader = BufReader::new(Segments(&data, sizes.clone()));
+            let request = super::super::Request::from(&mut reader, &Limits::default()).unwrap();
+            assert_eq!(request.content, b"hello world", "{:?}", sizes);
+
+            let next = super::super::Request::from(&mut reader, &Limits::default()).unwrap();
+            assert_eq!(next.path, "/next");
+        }
+    }
+
+    #[test]
+    fn refuses_short_and_large_bodies() {
+        let short = &REQUEST[..REQUEST.len() - 1];
+        let err = super::super::Request::from(&mut BufReader::new(short), &Limits::default()).unwrap_err();
+        assert!(matches!(err, ReadError::Closed));
+
+        let limits = Limits { max_body_size: 10, ..Limits::default() };
+        let err = super::super::Request::from(&mut BufReader::new(REQUEST), &limits).unwrap_err();
+        assert!(matches!(err, ReadError::BodyTooLarge));
+        assert_eq!(err.status(), StatusCode::Http413PayloadToolarge);
+
+        let limits = Limits { max_body_size: 11, ..Limits::default() };
+        assert!(super::super::Request::from(&mut BufReader::new(REQUEST), &limits).is_ok());
+    }
+}
@@ -0,0 +1,439 @@
+use std::sync::Arc;
+
+use super::http::Method;
+use super::middleware::Middleware;
+use super::{Request, Response};
+
+pub type Route = dyn Fn(Request) -> Response + Send + Sync;
+
+/// Parameters captured from the path, in pattern order.
+pub type Params = Vec<(String, String)>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Static(String),
+    Param(String),
+    Wildcard(String),
+}
+
+impl Segment {
+    /// Lower rank wins when several patterns match the same path.
+    fn rank(&self) -> u8 {
+        match self {
+            Segment::Static(_) => 0,
+            Segment::Param(_) => 1,
+            Segment::Wildcard(_) => 2,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Entry {
+    method: Method,
+    segments: Vec<Segment>,
+    handler: Arc<Route>,
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+/// Result of looking up a request in a [`Router`].
+pub enum Match<'a> {
+    /// A route matched both the method and the path, with the middleware to run around it.
+    Found(&'a Arc<Route>, Params, Vec<Arc<dyn Middleware>>),
+    /// The path matched but none of the routes accept the method.
+    MethodNotAllowed(Vec<Method>),
+    NotFound,
+}
+
+/// Method-aware request router.
+///
+/// Patterns are split on `/`; a segment starting with `:` captures one non-empty path
+/// segment and a segment starting with `*` captures the rest of the path, which may be
+/// empty: `/files/*path` matches `/files` and `/files/` as well. When several patterns match,
+/// static segments win over parameters and parameters win over wildcards, left to right.
+///
+/// ```ignore
+/// let router = Router::new()
+///     .get("/users/:id", show_user)
+///     .get("/users/me", show_current_user)
+///     .get("/files/*path", download);
+/// ```
+///
+/// Routes sharing a prefix and middleware can be grouped in their own router and mounted
+/// with [`Router::nest`].
+///
+/// ```ignore
+/// let admin = Router::new()
+///     .get("/stats", show_stats)
+///     .middleware(require_admin);
+///
+/// let router = Router::new()
+///     .get("/", home)
+///     .nest("/admin", admin);
+/// ```
+#[derive(Clone, Default)]
+pub struct Router {
+    entries: Vec<Entry>,
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl Router {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `handler` for `method` requests matching `pattern`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a wildcard segment is not the last segment of the pattern.
+    pub fn route<F>(mut self, method: Method, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        let segments = parse_pattern(pattern);
+
+        assert!(
+            segments.iter().rev().skip(1).all(|segment| !matches!(segment, Segment::Wildcard(_))),
+            "wildcard must be the last segment of route `{}`", pattern
+        );
+
+        self.entries.push(Entry {
+            method,
+            segments,
+            handler: Arc::new(handler),
+            middleware: vec![],
+        });
+        self
+    }
+
+    /// Runs `middleware` around every route of this router, including nested ones.
+    ///
+    /// Middleware runs in registration order, before the middleware of nested groups.
+    pub fn middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Mounts the routes of `group` under `prefix`, keeping the middleware of `group`
+    /// limited to them.
+    pub fn nest(mut self, prefix: &str, group: Router) -> Self {
+
+        let prefix = parse_pattern(prefix.trim_end_matches('/'));
+
+        for entry in group.entries {
+
+            let mut segments: Vec<Segment> = prefix.iter().filter(|segment| **segment != Segment::Static(String::new())).cloned().collect();
+
+            // the root of the group is the prefix itself
+            if entry.segments != [Segment::Static(String::new())] || segments.is_empty() {
+                segments.extend(entry.segments);
+            }
+
+            let mut middleware = group.middleware.clone();
+            middleware.extend(entry.middleware);
+
+            self.entries.push(Entry { segments, middleware, ..entry });
+        }
+        self
+    }
+
+    pub fn get<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Get, pattern, handler)
+    }
+
+    pub fn post<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Post, pattern, handler)
+    }
+
+    pub fn put<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Put, pattern, handler)
+    }
+
+    pub fn delete<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Delete, pattern, handler)
+    }
+
+    pub fn patch<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Patch, pattern, handler)
+    }
+
+    /// Registers a HEAD handler, without one HEAD requests are answered by the GET route.
+    pub fn head<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Head, pattern, handler)
+    }
+
+    /// Registers an OPTIONS handler, without one the server answers with the allowed methods.
+    pub fn options<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Options, pattern, handler)
+    }
+
+    /// Methods with at least one registered route, in registration order.
+    pub fn methods(&self) -> Vec<Method> {
+
+        let mut methods: Vec<Method> = vec![];
+
+        for entry in &self.entries {
+            if !methods.contains(&entry.method) {
+                methods.push(entry.method.clone());
+            }
+        }
+
+        methods
+    }
+
+    /// Finds the best route for `method` and `path`.
+    ///
+    /// HEAD requests fall back to the GET routes when no HEAD route matches.
+    pub fn find(&self, method: &Method, path: &str) -> Match<'_> {
+        match (method, self.find_exact(method, path)) {
+            (Method::Head, Match::MethodNotAllowed(allowed)) if allowed.contains(&Method::Get) => {
+                self.find_exact(&Method::Get, path)
+            }
+            (_, found) => found
+        }
+    }
+
+    fn find_exact(&self, method: &Method, path: &str) -> Match<'_> {
+
+        let path_segments = split_path(path);
+
+        let mut best: Option<(Vec<u8>, &Entry, Params)> = None;
+        let mut allowed: Vec<Method> = vec![];
+
+        for entry in &self.entries {
+
+            let params = match match_segments(&entry.segments, &path_segments) {
+                Some(params) => params,
+                None => continue
+            };
+
+            if entry.method != *method {
+                if !allowed.contains(&entry.method) {
+                    allowed.push(entry.method.clone());
+                }
+                continue;
+            }
+
+            let rank: Vec<u8> = entry.segments.iter().map(Segment::rank).collect();
+
+            if best.as_ref().is_none_or(|(best_rank, _, _)| rank < *best_rank) {
+                best = Some((rank, entry, params));
+            }
+        }
+
+        match best {
+            Some((_, entry, params)) => Match::Found(
+                &entry.handler,
+                params,
+                self.middleware.iter().chain(&entry.middleware).cloned().collect()
+            ),
+            None if !allowed.is_empty() => Match::MethodNotAllowed(allowed),
+            None => Match::NotFound
+        }
+    }
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    path.strip_prefix('/').unwrap_or(path).split('/').collect()
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    split_path(pattern)
+        .into_iter()
+        .map(|segment| {
+            if let Some(name) = segment.strip_prefix(':') {
+                Segment::Param(name.to_owned())
+            } else if let Some(name) = segment.strip_prefix('*') {
+                Segment::Wildcard(name.to_owned())
+            } else {
+                Segment::Static(segment.to_owned())
+            }
+        })
+        .collect()
+}
+
+fn match_segments(pattern: &[Segment], path: &[&str]) -> Option<Params> {
+
+    let mut params = vec![];
+
+    for (index, segment) in pattern.iter().enumerate() {
+        match segment {
+            // an empty remainder is captured as well
+            Segment::Wildcard(name) => {
+                params.push((name.clone(), path.get(index..).unwrap_or_default().join("/")));
+                return Some(params);
+            }
+            Segment::Param(name) => match path.get(index) {
+                Some(value) if !value.is_empty() => params.push((name.clone(), (*value).to_owned())),
+                _ => return None
+            },
+            Segment::Static(expected) => {
+                if path.get(index) != Some(&expected.as_str()) {
+                    return None;
+                }
+            }
+        }
+    }
+
+    if pattern.len() == path.len() {
+        Some(params)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Limits, Server};
+    use super::*;
+
+    fn route(name: &'static str) -> impl Fn(Request) -> Response + Send + Sync {
+        move |request: Request| {
+            let params: Vec<String> = request.path_params.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+            Response::text(format!("{} {}", name, params.join(" ")))
+        }
+    }
+
+    /// Body of the response `router` gives for `method` and `path`, or its status.
+    fn answer(router: &Router, method: Method, path: &str) -> String {
+        let request = Request::from(&mut format!("{} {} HTTP/1.1\r\n\r\n", method.as_str(), path).as_bytes(), &Limits::default()).unwrap();
+
+        let response = Server::new().routes(router.clone()).respond(request);
+
+        match response.status {
+            super::super::StatusCode::Http200Ok => String::from_utf8_lossy(response.body.as_bytes()).trim_end().to_owned(),
+            status => status.as_str().to_owned()
+        }
+    }
+
+    fn allowed(router: &Router, method: Method, path: &str) -> Option<Vec<Method>> {
+        match router.find(&method, path) {
+            Match::MethodNotAllowed(allowed) => Some(allowed),
+            _ => None
+        }
+    }
+
+    #[test]
+    fn prefers_static_over_params_over_wildcards() {
+        // registered in the opposite order of their priority
+        let router = Router::new()
+            .get("/users/*rest", route("rest"))
+            .get("/users/:id", route("id"))
+            .get("/users/me", route("me"))
+            .get("/users/:id/posts", route("posts"))
+            .get("/:any/me", route("any"));
+
+        assert_eq!(answer(&router, Method::Get, "/users/me"), "me");
+        assert_eq!(answer(&router, Method::Get, "/users/42"), "id id=42");
+        assert_eq!(answer(&router, Method::Get, "/users/42/posts"), "posts id=42");
+        assert_eq!(answer(&router, Method::Get, "/users/42/other/x"), "rest rest=42/other/x");
+        // earlier segments decide first
+        assert_eq!(answer(&router, Method::Get, "/users/me/posts"), "posts id=me");
+        assert_eq!(answer(&router, Method::Get, "/groups/me"), "any any=groups");
+        assert_eq!(answer(&router, Method::Get, "/groups"), "404 Not Found");
+    }
+
+    #[test]
+    fn matches_an_empty_wildcard_remainder() {
+        let router = Router::new().get("/files/*path", route("files"));
+
+        assert_eq!(answer(&router, Method::Get, "/files"), "files path=");
+        assert_eq!(answer(&router, Method::Get, "/files/"), "files path=");
+        assert_eq!(answer(&router, Method::Get, "/files/a/b/"), "files path=a/b/");
+        assert_eq!(answer(&router, Method::Get, "/filesystem"), "404 Not Found");
+    }
+
+    #[test]
+    fn requires_non_empty_params() {
+        let router = Router::new().get("/users/:id", route("id"));
+
+        assert_eq!(answer(&router, Method::Get, "/users/"), "404 Not Found");
+        assert_eq!(answer(&router, Method::Get, "/users"), "404 Not Found");
+        assert_eq!(answer(&router, Method::Get, "/users/1/"), "404 Not Found");
+    }
+
+    #[test]
+    fn decodes_captured_values() {
+        let router = Router::new().get("/files/:name", route("name")).get("/raw/*path", route("raw"));
+
+        assert_eq!(answer(&router, Method::Get, "/files/a%20b%2Fc"), "name name=a b/c");
+        assert_eq!(answer(&router, Method::Get, "/files/%C3%A9+"), "name name=é+");
+        // left as sent when it is not valid percent-encoding
+        assert_eq!(answer(&router, Method::Get, "/files/100%"), "name name=100%");
+        // an encoded slash does not split segments
+        assert_eq!(answer(&router, Method::Get, "/raw/a%2Fb/c"), "raw path=a/b/c");
+        assert_eq!(answer(&router, Method::Get, "/files/a%2Fb/c"), "404 Not Found");
+    }
+
+    #[test]
+    fn reports_the_allowed_methods() {
+        let router = Router::new()
+            .get("/items", route("list"))
+            .post("/items", route("create"))
+            .delete("/items/:id", route("delete"))
+            .put("/items/:id", route("replace"))
+            .patch("/items/*rest", route("patch"));
+
+        // the wildcard matches the empty remainder of `/items` too
+        assert_eq!(allowed(&router, Method::Put, "/items"), Some(vec![Method::Get, Method::Post, Method::Patch]));
+        assert_eq!(allowed(&router, Method::Get, "/items/1"), Some(vec![Method::Delete, Method::Put, Method::Patch]));
+        assert_eq!(allowed(&router, Method::Get, "/other"), None);
+        // HEAD falls back to GET
+        assert!(matches!(router.find(&Method::Head, "/items"), Match::Found(..)));
+        assert_eq!(allowed(&router, Method::Head, "/items/1"), Some(vec![Method::Delete, Method::Put, Method::Patch]));
+
+        assert_eq!(answer(&router, Method::Put, "/items"), "405 Method Not Allowed");
+        let request = Request::from(&mut b"PUT /items HTTP/1.1\r\n\r\n".as_slice(), &Limits::default()).unwrap();
+        let response = Server::new().routes(router).respond(request);
+        assert_eq!(response.headers.get("Allow"), Some("GET, POST, PATCH, HEAD, OPTIONS"));
+    }
+
+    #[test]
+    fn joins_nested_prefixes() {
+        let group = || Router::new().get("/", route("root")).get("/stats", route("stats")).get("/:id", route("id"));
+
+        let router = Router::new()
+            .nest("/admin", group())
+            .nest("/api/v1/", group())
+            .nest("/", Router::new().get("/top", route("top")))
+            .nest("/users/:user", Router::new().get("/posts/*rest", route("posts")));
+
+        assert_eq!(answer(&router, Method::Get, "/admin"), "root");
+        assert_eq!(answer(&router, Method::Get, "/admin/stats"), "stats");
+        assert_eq!(answer(&router, Method::Get, "/admin/7"), "id id=7");
+        assert_eq!(answer(&router, Method::Get, "/api/v1"), "root");
+        assert_eq!(answer(&router, Method::Get, "/api/v1/stats"), "stats");
+        assert_eq!(answer(&router, Method::Get, "/top"), "top");
+        assert_eq!(answer(&router, Method::Get, "/users/ann/posts/1/2"), "posts user=ann rest=1/2");
+        assert_eq!(answer(&router, Method::Get, "/adminstats"), "404 Not Found");
+        assert_eq!(answer(&router, Method::Get, "/stats"), "404 Not Found");
+    }
+
+    #[test]
+    #[should_panic(expected = "wildcard must be the last segment")]
+    fn refuses_wildcards_before_the_end() {
+        let _ = Router::new().get("/files/*path/edit", route("edit"));
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long in-flight requests may take to finish once shutdown is requested.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Stops a running [`Server`](super::Server) from another thread.
+///
+/// After [`ShutdownHandle::shutdown`] the server stops accepting connections and closes
+/// idle ones. Requests being answered are finished, then their connections are closed.
+/// Whatever is still open after the shutdown timeout is closed forcibly, and `run`
+/// returns once the workers are joined.
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+    shared: Arc<Shared>,
+}
+
+#[derive(Default)]
+struct Shared {
+    requested: AtomicBool,
+    next_id: AtomicU64,
+    address: Mutex<Option<SocketAddr>>,
+    connections: Mutex<HashMap<u64, Connection>>,
+    drained: Condvar,
+}
+
+struct Connection {
+    stream: TcpStream,
+    /// Waiting for the next request after answering one.
+    idle: bool,
+}
+
+/// Registration of an accepted connection, removed again on drop.
+pub struct Tracked {
+    id: u64,
+    shared: Arc<Shared>,
+}
+
+impl ShutdownHandle {
+
+    /// Asks the server to stop, returns immediately.
+    pub fn shutdown(&self) {
+
+        self.shared.requested.store(true, Ordering::SeqCst);
+
+        // wake the acceptor blocked in accept()
+        if let Some(address) = *self.shared.address.lock().unwrap() {
+            let _ = TcpStream::connect_timeout(&loopback(address), Duration::from_secs(1));
+        }
+
+        // connections still queued or answering a request are left to finish
+        for connection in self.shared.connections.lock().unwrap().values() {
+            if connection.idle {
+                let _ = connection.stream.shutdown(Shutdown::Both);
+            }
+        }
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.shared.requested.load(Ordering::SeqCst)
+    }
+
+    /// Remembers where the server listens, returns `false` if shutdown was already requested.
+    pub fn listening(&self, address: SocketAddr) -> bool {
+        *self.shared.address.lock().unwrap() = Some(address);
+        !self.is_shutdown()
+    }
+
+    /// Address the server listens on, `None` until it does.
+    pub fn address(&self) -> Option<SocketAddr> {
+        *self.shared.address.lock().unwrap()
+    }
+
+    /// Registers an accepted connection so shutdown can close it.
+    pub fn track(&self, stream: &TcpStream) -> std::io::Result<Tracked> {
+
+        let id = self.shared.next_id.fetch_add(1, Ordering::Relaxed);
+        let connection = Connection { stream: stream.try_clone()?, idle: false };
+
+        self.shared.connections.lock().unwrap().insert(id, connection);
+
+        Ok(Tracked { id, shared: Arc::clone(&self.shared) })
+    }
+
+    /// Waits up to `timeout` for all connections to close, then closes the rest.
+    pub fn drain(&self, timeout: Duration) {
+
+        let deadline = Instant::now() + timeout;
+        let mut connections = self.shared.connections.lock().unwrap();
+
+        while !connections.is_empty() {
+            let now = Instant::now();
+
+            if now >= deadline {
+                break;
+            }
+            connections = self.shared.drained.wait_timeout(connections, deadline - now).unwrap().0;
+        }
+
+        for connection in connections.values() {
+            let _ = connection.stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+impl Tracked {
+
+    /// Marks the connection as answering a request, so shutdown lets it finish.
+    pub fn busy(&self) {
+        if let Some(connection) = self.shared.connections.lock().unwrap().get_mut(&self.id) {
+            connection.idle = false;
+        }
+    }
+
+    /// Marks the connection as waiting for its next request, returns `false` if it
+    /// should close because of shutdown.
+    pub fn idle(&self) -> bool {
+        let mut connections = self.shared.connections.lock().unwrap();
+
+        if let Some(connection) = connections.get_mut(&self.id) {
+            connection.idle = true;
+        }
+        !self.shared.requested.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        let mut connections = self.shared.connections.lock().unwrap();
+        connections.remove(&self.id);
+
+        if connections.is_empty() {
+            self.shared.drained.notify_all();
+        }
+    }
+}
+
+/// Address to connect to for reaching a listener bound to `address`.
+fn loopback(address: SocketAddr) -> SocketAddr {
+    match address.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => SocketAddr::new(Ipv4Addr::LOCALHOST.into(), address.port()),
+        IpAddr::V6(ip) if ip.is_unspecified() => SocketAddr::new(Ipv6Addr::LOCALHOST.into(), address.port()),
+        _ => address
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::super::tests::{exchange, start};
+    use super::super::{Request, Response, Router, Server};
+
+    #[test]
+    fn answers_requests_in_flight_and_queued() {
+
+        let routes = Router::new().get("/slow", |_: Request| {
+            thread::sleep(Duration::from_millis(400));
+            Response::text("done")
+        });
+        // a single worker, so the second connection waits in the queue
+        let (address, handle, server) = start(Server::new().max_connections(1).routes(routes));
+
+        let request = b"GET /slow HTTP/1.1\r\nHost: x\r\n\r\n";
+        let in_flight = thread::spawn(move || exchange(address, request));
+        thread::sleep(Duration::from_millis(100));
+        let queued = thread::spawn(move || exchange(address, request));
+        thread::sleep(Duration::from_millis(100));
+
+        handle.shutdown();
+
+        for client in [in_flight, queued] {
+            let response = client.join().unwrap();
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+            assert!(response.contains("Connection: close\r\n"), "{}", response);
+            assert!(response.ends_with("done"), "{}", response);
+        }
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn closes_idle_connections() {
+
+        let (address, handle, server) = start(Server::new().max_connections(2).keep_alive_timeout(Duration::from_secs(30)));
+
+        let idle = thread::spawn(move || exchange(address, b"OPTIONS * HTTP/1.1\r\nHost: x\r\n\r\n"));
+        thread::sleep(Duration::from_millis(200));
+
+        let started = Instant::now();
+        handle.shutdown();
+
+        assert!(idle.join().unwrap().starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        server.join().unwrap();
+    }
+}